@@ -0,0 +1,34 @@
+/// Album management
+///
+/// An album is an ordered collection of pictures, similar to a playlist in
+/// WMP Legacy. A picture can be in any number of albums, and removing it from
+/// an album does not remove it from the library. Albums are created, modified
+/// and listed through the Library.
+#[derive(Debug, Clone)]
+pub struct Album {
+    pub(crate) id: u32,
+    pub(crate) name: String,
+    pub(crate) cover: Option<u32>,
+    pub(crate) picture_count: u32,
+}
+
+// Instance methods
+impl Album {
+    /// Get the database id of the album.
+    pub fn get_id(&self) -> u32 {
+        self.id
+    }
+    /// Get the name of the album.
+    pub fn get_name(&self) -> String {
+        self.name.clone()
+    }
+    /// Get the id of the picture explicitly chosen as the album cover, if any.
+    /// Use Library::get_album_cover() to also fall back to the first picture.
+    pub fn get_cover(&self) -> Option<u32> {
+        self.cover
+    }
+    /// Get the number of pictures in the album.
+    pub fn get_picture_count(&self) -> u32 {
+        self.picture_count
+    }
+}
@@ -0,0 +1,32 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Cancellation of long running tasks
+///
+/// Clones share the same state, so a client can keep one and hand the other
+/// to the task, then cancel it from another thread, for instance when the
+/// user closes a window. The task stops as soon as possible, keeping the work
+/// already done.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+// Static methods
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken::default()
+    }
+}
+
+// Instance methods
+impl CancelToken {
+    /// Ask the task to stop.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+    /// Whether cancel() was called on this token or one of its clones.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
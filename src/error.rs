@@ -1,18 +1,61 @@
 use thiserror::Error;
-use rusqlite;
-use toml;
 
 #[derive(Error, Debug, PartialEq)]
 pub enum LumenzaError {
     #[error("Picture already in library")]
     PictureAlreadyInLibrary(),
 
+    #[error("Picture not found in library")]
+    PictureNotFound(),
+
+    #[error("Rating must be between 0 and 5")]
+    InvalidRating(),
+
+    #[error("Album not found")]
+    AlbumNotFound(),
+
+    #[error("Picture already in album")]
+    PictureAlreadyInAlbum(),
+
+    #[error("Picture not in album")]
+    PictureNotInAlbum(),
+
+    #[error("Stack not found")]
+    StackNotFound(),
+
+    #[error("Picture already in a stack")]
+    PictureAlreadyInStack(),
+
+    #[error("Picture not in stack")]
+    PictureNotInStack(),
+
+    #[error("A stack needs at least two distinct pictures, including its top")]
+    InvalidStack(),
+
+    #[error("Tag not found")]
+    TagNotFound(),
+
+    #[error("Tag already exists")]
+    TagAlreadyExists(),
+
+    #[error("Invalid tag name")]
+    InvalidTagName(),
+
+    #[error("Tag cannot be merged into itself or its descendants")]
+    InvalidTagMerge(),
+
+    #[error("Folder not in library")]
+    FolderNotFound(),
+
     #[error("File not found")]
     FileNotFound(),
 
     #[error("std::io::error occurred")] // std::io::error doesn't implement PartialEq -_-
     IoError(),
 
+    #[error("Database version {0} is newer than the supported version {1}")]
+    DatabaseTooNew(u32, u32),
+
     #[error("Database error: {0}")]
     DatabaseError(#[from] rusqlite::Error),
 
@@ -27,4 +70,7 @@ pub enum LumenzaError {
 
     #[error("Image error")]
     ImageError(),
+
+    #[error("Thumbnail profiles must have a size and a quality between 1 and 100")]
+    InvalidThumbnailProfile(),
 }
@@ -0,0 +1,53 @@
+use std::path::PathBuf;
+
+use crate::picture::Picture;
+use crate::watch::WatchEvent;
+
+/// A change made to the library
+///
+/// Sent to the receivers given by Library::subscribe() once the change is in
+/// the database, so that a client can update its views without listing the
+/// whole library again. Pictures carry their new state.
+#[derive(Debug, Clone)]
+pub enum LibraryEvent {
+    /// A picture was added to the library, or its file came back after going
+    /// missing.
+    PictureAdded(Picture),
+    /// The file of a picture is gone. The picture is marked as missing,
+    /// keeping its tags and albums, until purge_missing() is called.
+    PictureRemoved(Picture),
+    /// A picture was removed from the library for good, by purge_missing()
+    /// or remove_folder().
+    PictureDeleted(Picture),
+    /// The file of a picture was moved or renamed.
+    PictureMoved { from: PathBuf, picture: Picture },
+    /// The default thumbnail of a picture was generated or changed.
+    ThumbnailReady(Picture),
+    /// The metadata, rating or tags of a picture changed, for instance after
+    /// its file was edited.
+    MetadataChanged(Picture),
+    /// An album was created, renamed, deleted, or its pictures or cover
+    /// changed. Get it again with Library::get_album(), which fails with
+    /// AlbumNotFound if it was deleted.
+    AlbumChanged(u32),
+    /// A stack was created or deleted, or its pictures or top changed. Get
+    /// it again with Library::get_stack(), which fails with StackNotFound if
+    /// it was deleted.
+    StackChanged(u32),
+    /// A tag was created, renamed, merged into another or deleted, along
+    /// with its descendants. Pictures whose tags changed are sent as
+    /// MetadataChanged. Get the tag again with Library::get_tag(), which
+    /// fails with TagNotFound if it was deleted.
+    TagChanged(u32),
+}
+
+impl From<WatchEvent> for LibraryEvent {
+    fn from(event: WatchEvent) -> Self {
+        match event {
+            WatchEvent::Added(picture) => LibraryEvent::PictureAdded(picture),
+            WatchEvent::Removed(picture) => LibraryEvent::PictureRemoved(picture),
+            WatchEvent::Moved { from, picture } => LibraryEvent::PictureMoved { from, picture },
+            WatchEvent::Modified(picture) => LibraryEvent::MetadataChanged(picture),
+        }
+    }
+}
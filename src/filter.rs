@@ -0,0 +1,378 @@
+use chrono::NaiveDateTime;
+use path_absolutize::Absolutize;
+use rusqlite::types::Value;
+use std::ops::{BitAnd, BitOr, Not};
+use std::path::{Path, PathBuf};
+
+use crate::album::Album;
+use crate::media::MediaKind;
+use crate::tag::Tag;
+
+/// Predicates for searching the library
+///
+/// Filters are composed with `&` (and), `|` (or) and `!` (not), and are
+/// compiled into a single SQL query, so searching is done entirely by the
+/// database. For example, pictures rated 4 or more taken in Italy, but not
+/// with a phone:
+///
+/// ```ignore
+/// let filter = Filter::MinRating(4)
+///     & Filter::tag(&italy)
+///     & !Filter::Camera("iPhone".to_string());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    /// Matches every picture.
+    All,
+    /// Pictures taken between the two dates, inclusive. Either end can be
+    /// left open. Pictures without a capture date never match.
+    DateTaken(Option<NaiveDateTime>, Option<NaiveDateTime>),
+    /// Media of the given kind.
+    Kind(MediaKind),
+    /// Pictures inside the folder, or any of its subfolders.
+    Folder(PathBuf),
+    /// Pictures with the tag (by id). If `descendants` is set, pictures
+    /// tagged with any tag below it match as well.
+    Tag { id: u32, descendants: bool },
+    /// Pictures in the album (by id).
+    Album(u32),
+    /// Pictures rated at least this much.
+    MinRating(u8),
+    /// Pictures rated at most this much.
+    MaxRating(u8),
+    /// Pictures of the given mime type. A type ending in `/*`, like
+    /// `image/*`, matches the whole category.
+    MimeType(String),
+    /// Pictures whose camera make or model contains the text, ignoring case.
+    Camera(String),
+    /// Pictures at least this wide and this tall, in pixels.
+    MinDimensions(u32, u32),
+    /// Pictures at most this wide and this tall, in pixels.
+    MaxDimensions(u32, u32),
+    /// Pictures whose filename or one of their tags contains the text,
+    /// ignoring case.
+    Text(String),
+    /// Pictures whose file was missing the last time the library was
+    /// verified.
+    Missing,
+    /// Camera RAW files.
+    Raw,
+    /// Live Photos and motion photos: stills with a video part, see
+    /// Library::get_live_video().
+    LivePhotos,
+    /// Videos paired with the still of a Live Photo. Use
+    /// `!Filter::LiveVideos` to show Live Photos as a single item.
+    LiveVideos,
+    /// Pictures at the top of their stack, and pictures that are not
+    /// stacked. Hides the other pictures of every stack.
+    StackTops,
+    /// All of the filters match. An empty list matches every picture.
+    And(Vec<Filter>),
+    /// Any of the filters match. An empty list matches no picture.
+    Or(Vec<Filter>),
+    /// The filter does not match.
+    Not(Box<Filter>),
+}
+
+/// Order in which query results are returned.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sort {
+    /// The order in which pictures were added to the library.
+    Imported,
+    /// Full path of the file.
+    Filename,
+    /// Capture date. Pictures without one come last.
+    DateTaken,
+    /// User rating.
+    Rating,
+}
+
+/// Direction of the sort.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Order {
+    Ascending,
+    Descending,
+}
+
+/// A filter along with sorting and pagination
+///
+/// Built with chained calls, and run with Library::query().
+///
+/// ```ignore
+/// let query = Query::new()
+///     .filter(Filter::MinRating(3))
+///     .sort(Sort::DateTaken, Order::Descending)
+///     .limit(100)
+///     .offset(200);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    pub(crate) filter: Filter,
+    pub(crate) sort: Sort,
+    pub(crate) order: Order,
+    pub(crate) limit: Option<u32>,
+    pub(crate) offset: u32,
+}
+
+// Static methods
+impl Filter {
+    /// Pictures with the tag or any of its descendants.
+    pub fn tag(tag: &Tag) -> Self {
+        Filter::Tag {
+            id: tag.get_id(),
+            descendants: true,
+        }
+    }
+    /// Pictures in the album.
+    pub fn album(album: &Album) -> Self {
+        Filter::Album(album.get_id())
+    }
+    /// Pictures inside the folder. Relative paths are resolved against the
+    /// current directory, the same way process_folder() does.
+    pub fn folder(folder: &Path) -> Self {
+        Filter::Folder(folder.absolutize().unwrap_or_default().into_owned())
+    }
+}
+
+// Instance methods
+impl Filter {
+    /// Compile the filter into an SQL expression on the `pictures` and
+    /// `picture_metadata` tables, pushing its parameters in order.
+    pub(crate) fn to_sql(&self, params: &mut Vec<Value>) -> String {
+        match self {
+            Filter::All => "1".to_string(),
+            Filter::DateTaken(from, to) => {
+                let mut parts = vec!["picture_metadata.date_taken IS NOT NULL".to_string()];
+                if let Some(from) = from {
+                    params.push(Value::Text(from.format(DATE_FORMAT).to_string()));
+                    parts.push("picture_metadata.date_taken >= ?".to_string());
+                }
+                if let Some(to) = to {
+                    params.push(Value::Text(to.format(DATE_FORMAT).to_string()));
+                    parts.push("picture_metadata.date_taken <= ?".to_string());
+                }
+                format!("({})", parts.join(" AND "))
+            }
+            Filter::Kind(kind) => {
+                params.push(Value::Integer(kind.to_i64()));
+                "pictures.kind = ?".to_string()
+            }
+            Filter::Folder(folder) => {
+                let mut prefix = folder.to_string_lossy().to_string();
+                if !prefix.ends_with(std::path::MAIN_SEPARATOR) {
+                    prefix.push(std::path::MAIN_SEPARATOR);
+                }
+                params.push(Value::Integer(prefix.chars().count() as i64));
+                params.push(Value::Text(prefix));
+                "substr(pictures.filename, 1, ?) = ?".to_string()
+            }
+            Filter::Tag { id, descendants } => {
+                params.push(Value::Integer(*id as i64));
+                params.push(Value::Integer(*descendants as i64));
+                "pictures.id IN (
+                    WITH RECURSIVE subtree(id) AS (
+                        SELECT ? UNION SELECT tags.id FROM tags JOIN subtree ON tags.parent = subtree.id AND ?
+                    )
+                    SELECT picture_tags.picture FROM picture_tags JOIN subtree ON picture_tags.tag = subtree.id
+                )"
+                .to_string()
+            }
+            Filter::Album(id) => {
+                params.push(Value::Integer(*id as i64));
+                "pictures.id IN (SELECT picture FROM album_pictures WHERE album = ?)".to_string()
+            }
+            Filter::MinRating(rating) => {
+                params.push(Value::Integer(*rating as i64));
+                "pictures.rating >= ?".to_string()
+            }
+            Filter::MaxRating(rating) => {
+                params.push(Value::Integer(*rating as i64));
+                "pictures.rating <= ?".to_string()
+            }
+            Filter::MimeType(mime) => match mime.strip_suffix('*') {
+                Some(category) => {
+                    params.push(Value::Integer(category.chars().count() as i64));
+                    params.push(Value::Text(category.to_string()));
+                    "substr(pictures.mime, 1, ?) = ?".to_string()
+                }
+                None => {
+                    params.push(Value::Text(mime.clone()));
+                    "pictures.mime = ?".to_string()
+                }
+            },
+            Filter::Camera(text) => {
+                params.push(Value::Text(text.clone()));
+                "instr(lower(coalesce(picture_metadata.camera_make, '') || ' ' ||
+                    coalesce(picture_metadata.camera_model, '')), lower(?)) > 0"
+                    .to_string()
+            }
+            Filter::MinDimensions(width, height) => {
+                params.push(Value::Integer(*width as i64));
+                params.push(Value::Integer(*height as i64));
+                "(picture_metadata.width >= ? AND picture_metadata.height >= ?)".to_string()
+            }
+            Filter::MaxDimensions(width, height) => {
+                params.push(Value::Integer(*width as i64));
+                params.push(Value::Integer(*height as i64));
+                "(picture_metadata.width <= ? AND picture_metadata.height <= ?)".to_string()
+            }
+            Filter::Text(text) => {
+                params.push(Value::Text(text.clone()));
+                params.push(Value::Text(text.clone()));
+                "(instr(lower(pictures.filename), lower(?)) > 0 OR pictures.id IN (
+                    SELECT picture_tags.picture FROM picture_tags JOIN tags ON tags.id = picture_tags.tag
+                    WHERE instr(lower(tags.name), lower(?)) > 0
+                ))"
+                .to_string()
+            }
+            Filter::Missing => "pictures.missing = 1".to_string(),
+            Filter::Raw => "pictures.raw = 1".to_string(),
+            Filter::LivePhotos => "pictures.id IN (SELECT picture FROM live_photos)".to_string(),
+            Filter::LiveVideos => {
+                "pictures.id IN (SELECT video FROM live_photos WHERE video IS NOT NULL)".to_string()
+            }
+            Filter::StackTops => format!(
+                "(pictures.id NOT IN (SELECT picture FROM stack_pictures)
+                    OR pictures.id IN (SELECT {} FROM stacks))",
+                STACK_TOP
+            ),
+            Filter::And(filters) => combine(filters, " AND ", "1", params),
+            Filter::Or(filters) => combine(filters, " OR ", "0", params),
+            // Comparisons with NULL give NULL, which NOT leaves as is, so
+            // missing metadata is treated as not matching before negating.
+            Filter::Not(filter) => format!("NOT coalesce({}, 0)", filter.to_sql(params)),
+        }
+    }
+}
+
+/// SQL expression resolving the top of a stack: the chosen one, or the first
+/// picture of the stack if it was removed from the library.
+pub(crate) const STACK_TOP: &str = "coalesce(stacks.top,
+    (SELECT min(picture) FROM stack_pictures WHERE stack_pictures.stack = stacks.id))";
+
+/// Pictures of folders removed from the library with their metadata kept
+/// are hidden from every query.
+pub(crate) const NOT_DETACHED: &str = "pictures.detached = 0";
+
+/// Format used by SQLite (and rusqlite) to store dates, which sorts properly
+/// as text.
+const DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+fn combine(filters: &[Filter], operator: &str, empty: &str, params: &mut Vec<Value>) -> String {
+    if filters.is_empty() {
+        return empty.to_string();
+    }
+    let parts: Vec<String> = filters.iter().map(|filter| filter.to_sql(params)).collect();
+    format!("({})", parts.join(operator))
+}
+
+impl BitAnd for Filter {
+    type Output = Filter;
+
+    fn bitand(self, other: Filter) -> Filter {
+        match self {
+            Filter::And(mut filters) => {
+                filters.push(other);
+                Filter::And(filters)
+            }
+            _ => Filter::And(vec![self, other]),
+        }
+    }
+}
+
+impl BitOr for Filter {
+    type Output = Filter;
+
+    fn bitor(self, other: Filter) -> Filter {
+        match self {
+            Filter::Or(mut filters) => {
+                filters.push(other);
+                Filter::Or(filters)
+            }
+            _ => Filter::Or(vec![self, other]),
+        }
+    }
+}
+
+impl Not for Filter {
+    type Output = Filter;
+
+    fn not(self) -> Filter {
+        Filter::Not(Box::new(self))
+    }
+}
+
+impl Default for Query {
+    fn default() -> Self {
+        Query::new()
+    }
+}
+
+// Static methods
+impl Query {
+    /// A query matching every picture, in import order.
+    pub fn new() -> Self {
+        Query {
+            filter: Filter::All,
+            sort: Sort::Imported,
+            order: Order::Ascending,
+            limit: None,
+            offset: 0,
+        }
+    }
+}
+
+// Instance methods
+impl Query {
+    /// Only return pictures matching the filter.
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+    /// Set the order of the results. Ties are broken by import order.
+    pub fn sort(mut self, sort: Sort, order: Order) -> Self {
+        self.sort = sort;
+        self.order = order;
+        self
+    }
+    /// Return at most this many pictures.
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+    /// Skip this many pictures before returning results.
+    pub fn offset(mut self, offset: u32) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Compile the WHERE, ORDER BY, LIMIT and OFFSET clauses of the query.
+    /// Pictures of folders removed from the library are never matched.
+    pub(crate) fn to_sql(&self, params: &mut Vec<Value>) -> String {
+        let direction = match self.order {
+            Order::Ascending => "ASC",
+            Order::Descending => "DESC",
+        };
+        let order = match self.sort {
+            Sort::Imported => format!("pictures.id {}", direction),
+            Sort::Filename => format!("pictures.filename {}, pictures.id", direction),
+            Sort::DateTaken => format!(
+                "picture_metadata.date_taken {} NULLS LAST, pictures.id",
+                direction
+            ),
+            Sort::Rating => format!("pictures.rating {}, pictures.id", direction),
+        };
+
+        let mut sql = format!(
+            "WHERE {} AND ({}) ORDER BY {}",
+            NOT_DETACHED,
+            self.filter.to_sql(params),
+            order
+        );
+        // SQLite requires a limit when an offset is given, -1 meaning none.
+        params.push(Value::Integer(self.limit.map(i64::from).unwrap_or(-1)));
+        params.push(Value::Integer(self.offset as i64));
+        sql.push_str(" LIMIT ? OFFSET ?");
+        sql
+    }
+}
@@ -11,6 +11,7 @@
 //! are managed implicitly. Thumbnailiing can be done by the client, or by 
 //! Lumenza itself. This is useful when the target platform has a specific 
 //! thumbnailing library that is faster, or supports more specialized formats.
+//! Such a library can be plugged in by implementing the Thumbnailer trait.
 //! 
 //! Another note is that Lumenza does not interact with thumbnails beyound 
 //! storing the path to the thumbnail, unless requested explicitly by the client.
@@ -19,6 +20,9 @@
 /// Detailed error representation
 pub mod error;
 
+/// Searching, sorting and pagination
+pub mod filter;
+
 // Reexport as struct.
 mod library;
 pub use crate::library::Library;
@@ -27,5 +31,47 @@ pub use crate::library::Library;
 mod picture;
 pub use crate::picture::Picture;
 
+// Reexport as struct.
+mod album;
+pub use crate::album::Album;
+
+// Reexport as structs.
+mod media;
+pub use crate::media::{LiveVideo, Media, MediaKind, VideoInfo};
+
+// Reexport as struct and enum.
+mod stack;
+pub use crate::stack::{Stack, StackKind};
+
+// Reexport as struct.
+mod metadata;
+pub use crate::metadata::Metadata;
+
+// Reexport as struct.
+mod tag;
+pub use crate::tag::Tag;
+
+// Reexport as structs.
+mod report;
+pub use crate::report::{RemovalReport, ScanReport, ThumbnailReport, VerifyReport};
+
+// Reexport as structs and trait.
+mod thumbnail;
+pub use crate::thumbnail::{
+    ImageThumbnailer, ThumbnailCrop, ThumbnailFormat, ThumbnailProfile, Thumbnailer,
+};
+
+// Reexport as enum.
+mod event;
+pub use crate::event::LibraryEvent;
+
+// Reexport as enum.
+mod watch;
+pub use crate::watch::WatchEvent;
+
+// Reexport as struct.
+mod cancel;
+pub use crate::cancel::CancelToken;
+
 // Provide access to subsystems only in this crate.
 pub(crate) mod systems;
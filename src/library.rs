@@ -1,12 +1,39 @@
-use mime_guess;
+use notify::event::{AccessKind, AccessMode};
+use notify::{EventKind, RecursiveMode, Watcher};
 use path_absolutize::Absolutize;
-use std::path::PathBuf;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::album::Album;
+use crate::cancel::CancelToken;
 use crate::error::LumenzaError;
+use crate::event::LibraryEvent;
+use crate::filter::{Filter, Query};
+use crate::media::{self, LiveVideo, Media, MediaKind, VideoInfo};
+use crate::metadata::{self, Metadata};
 use crate::picture::Picture;
+use crate::report::{RemovalReport, ScanReport, ThumbnailReport, VerifyReport};
+use crate::stack::{self, Stack, StackKind};
 use crate::systems::config;
 use crate::systems::database;
+use crate::systems::hash;
+use crate::systems::thumbnails;
+use crate::tag::{self, Tag};
+use crate::thumbnail::{ImageThumbnailer, ThumbnailProfile, Thumbnailer};
+use crate::watch::WatchEvent;
+
+/// Time a file must stay untouched before its changes are applied, so that
+/// files being copied are only read once complete.
+const WATCH_SETTLE_TIME: Duration = Duration::from_millis(500);
+/// How often watch() checks for settled changes and cancellation.
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 /// Core management for multimedia libraries
 /// 
@@ -15,6 +42,8 @@ use crate::systems::database;
 pub struct Library {
     pub(crate) config: config::Config,
     pub(crate) database: database::Database,
+    pub(crate) thumbnailer: Box<dyn Thumbnailer>,
+    pub(crate) subscribers: RefCell<Vec<mpsc::Sender<LibraryEvent>>>,
 }
 
 // Static methods.
@@ -25,128 +54,1324 @@ impl Library {
     /// given paths. You set picture folders later for the sake of easier 
     /// integration with GUI applications.
     pub fn create(
-        config_path: &PathBuf,
-        thumbnails_path: &PathBuf,
-        database_path: &PathBuf,
+        config_path: &Path,
+        thumbnails_path: &Path,
+        database_path: &Path,
     ) -> Result<Self, LumenzaError> {
         // Initialize all files and folders.
         let db = database::Database::new(database_path)?;
-        let cfg = config::Config::new(config_path, &vec![], thumbnails_path, database_path)?;
+        let cfg = config::Config::new(config_path, &[], thumbnails_path, database_path)?;
         std::fs::create_dir_all(thumbnails_path).map_err(|_| LumenzaError::IoError())?;
 
         Ok(Library {
             config: cfg,
             database: db,
+            thumbnailer: Box::new(ImageThumbnailer),
+            subscribers: RefCell::new(Vec::new()),
         })
     }
 
     /// Open an existing library. The config file must exist, otherwise an error
     /// will be returned. The database file will be created if it does not exist, 
     /// and all pictures in the specified folders will be added to the database.
-    pub fn open(config_path: &PathBuf) -> Result<Self, LumenzaError> {
+    /// Databases from older versions of Lumenza are upgraded automatically,
+    /// while databases from newer versions are refused.
+    pub fn open(config_path: &Path) -> Result<Self, LumenzaError> {
         let cfg = config::Config::open(config_path)?;
         let db = database::Database::open(&cfg.get_database_path())?;
 
         Ok(Library {
             config: cfg,
             database: db,
+            thumbnailer: Box::new(ImageThumbnailer),
+            subscribers: RefCell::new(Vec::new()),
         })
     }
 }
 
 // Instance methods.
 impl Library {
-    /// Scan a folder for any images that are not in the library yet. If the
-    /// folder is not in the library, it will be added. Pictures that are marked
-    /// as independent but are in the given folder will be marked as children
-    /// of that folder. Does not generate thumbnails.
-    pub fn process_folder(&mut self, folder: &PathBuf) -> Result<(), LumenzaError> {
+    /// Scan a folder for any images and videos that are not in the library
+    /// yet. If the folder is not in the library, it will be added. Pictures
+    /// that are marked as independent but are in the given folder will be
+    /// marked as children of that folder. Files already in the library are
+    /// only read again if their size or modification time changed, in which
+    /// case their hash and metadata are refreshed. New pictures related to
+    /// each other, such as RAW+JPEG pairs, are stacked, see
+    /// stack_related_pictures(). Does not generate thumbnails.
+    ///
+    /// Fails with FileNotFound if the folder doesn't exist. Files that can't
+    /// be read are listed in the report instead of stopping the scan.
+    /// Details are logged through the `log` crate.
+    pub fn process_folder(&mut self, folder: &Path) -> Result<ScanReport, LumenzaError> {
         let mut image_paths: Vec<PathBuf> = Vec::new();
+        let mut report = ScanReport::default();
+        // Subscribers only hear of changes once they are committed.
+        let mut events = Vec::new();
         let full_path = folder.absolutize().unwrap_or_default().into_owned();
+        // A mistyped folder must not end up in the config.
+        if !full_path.is_dir() {
+            return Err(LumenzaError::FileNotFound());
+        }
 
         let folders = self.config.get_folders_paths();
         if !folders.iter().any(|x| x == &full_path) {
             self.config.add_folder(&full_path)?;
         }
 
-        let walker = WalkDir::new(full_path)
+        let walker = WalkDir::new(&full_path)
             .into_iter()
             .filter_entry(|e| !is_hidden_folder(e));
 
-        // Walk through all the files inside it, taking only files that are images.
+        // Files already in the library are recognized by their name, and
+        // only read again if their fingerprint changed.
+        let known = self.database.list_fingerprints()?;
+        let mut batch = ScanBatch::new(self)?;
+
+        // Walk through all the files inside it, taking only files that are images or videos.
         for entry in walker {
-            let entry = entry.map_err(|_| LumenzaError::FileNotFound())?;
-            if entry.file_type().is_file() {
-                let path = entry.path().to_path_buf();
-                let mime = mime_guess::from_path(&path).first_raw().unwrap_or_default();
-                // TODO: Add more image types.
-                if mime.starts_with("image/") {
-                    // We know that this is the full file path being pushed into the vector.
-                    image_paths.push(path.clone());
+            let entry = match entry {
+                Ok(entry) => entry,
+                // Folders that can't be listed don't stop the scan.
+                Err(err) => {
+                    let path = err.path().unwrap_or(&full_path).to_path_buf();
+                    log::warn!("Can't read {}: {}", path.display(), err);
+                    report.unreadable.push((path, LumenzaError::IoError()));
+                    continue;
                 }
+            };
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let path = entry.path().to_path_buf();
+            if let Some(&(id, fingerprint, missing)) = known.get(&path) {
+                match self.rescan_picture(id, &path, fingerprint, missing) {
+                    Ok(Some(picture)) => {
+                        log::debug!("Updated {}", path.display());
+                        events.push(match missing {
+                            true => LibraryEvent::PictureAdded(picture.clone()),
+                            false => LibraryEvent::MetadataChanged(picture.clone()),
+                        });
+                        report.updated.push(picture);
+                        batch.written(self)?;
+                    }
+                    Ok(None) => {
+                        log::trace!("Skipping over {}: already in library", path.display());
+                        report.skipped.push(path);
+                    }
+                    Err(LumenzaError::DatabaseError(err)) => {
+                        return Err(LumenzaError::DatabaseError(err))
+                    }
+                    Err(err) => {
+                        log::warn!("Can't read {}: {}", path.display(), err);
+                        report.unreadable.push((path, err));
+                    }
+                }
+                continue;
+            }
+            let mime = media::guess_mime(&path);
+            if MediaKind::from_mime(&mime).is_some() {
+                // We know that this is the full file path being pushed into the vector.
+                image_paths.push(path);
+            } else {
+                log::debug!("Skipping over {}: not a picture or video", path.display());
+                report.unsupported.push(path);
             }
         }
 
         // After making sure the picture doesn't exist yet, insert it into the database.
-        for image_path in &image_paths {
-            let picture = self.add_picture(image_path);
-
-            match picture {
-                Ok(_picture) => {
+        for image_path in image_paths {
+            let picture = match Picture::prepare(self, &image_path) {
+                Ok(picture) => picture,
+                Err(LumenzaError::PictureAlreadyInLibrary()) => {
+                    // The file may be back after being reported missing.
+                    if self.database.reattach_picture(&image_path)? {
+                        if let Some(picture) = self.database.get_picture_by_filename(&image_path)? {
+                            events.push(LibraryEvent::PictureAdded(picture));
+                        }
+                    }
+                    log::trace!("Skipping over {}: already in library", image_path.display());
+                    report.skipped.push(image_path);
                     continue;
                 }
+                Err(LumenzaError::DatabaseError(err)) => {
+                    return Err(LumenzaError::DatabaseError(err))
+                }
+                // Not a fatal error in this case, as we can just skip over the picture.
                 Err(err) => {
-                    // Not a fatal error in this case, as we can just skip over the picture.
-                    if err == LumenzaError::PictureAlreadyInLibrary() {
-                        println!("Skipping over picture: already in library");
-                        continue;
-                    } else {
-                        return Err(err);
-                    }
+                    log::warn!("Can't read {}: {}", image_path.display(), err);
+                    report.unreadable.push((image_path, err));
+                    continue;
+                }
+            };
+
+            // Files that were moved or renamed keep their entry, along with
+            // their tags and albums.
+            match self.find_moved_picture(&picture)? {
+                Some(mut moved) => {
+                    self.database
+                        .set_picture_filename(moved.id, &picture.filename)?;
+                    self.database
+                        .set_picture_fingerprint(moved.id, hash::fingerprint(&picture.filename))?;
+                    log::debug!(
+                        "Moved {} to {}",
+                        moved.filename.display(),
+                        picture.filename.display()
+                    );
+                    let from = std::mem::replace(&mut moved.filename, picture.filename);
+                    moved.missing = false;
+                    events.push(LibraryEvent::PictureMoved {
+                        from,
+                        picture: moved.clone(),
+                    });
+                    report.updated.push(moved);
+                }
+                None => {
+                    let picture = picture.insert(self)?;
+                    log::debug!("Added {}", picture.filename.display());
+                    events.push(LibraryEvent::PictureAdded(picture.clone()));
+                    report.added.push(picture);
                 }
             }
+            batch.written(self)?;
         }
-        Ok(())
+        batch.commit()?;
+
+        // Pictures the client unstacked before are only stacked again along
+        // with a new picture, and rescans adding nothing skip grouping.
+        if !report.added.is_empty() {
+            let added: HashSet<u32> = report.added.iter().map(|picture| picture.id).collect();
+            let stacks = self.write_related_stacks(|group| {
+                group.iter().any(|picture| added.contains(&picture.id))
+            })?;
+            events.extend(stacks.into_iter().map(LibraryEvent::StackChanged));
+        }
+        log::info!(
+            "Scanned {}: {} added, {} updated, {} skipped, {} unreadable, {} unsupported",
+            full_path.display(),
+            report.added.len(),
+            report.updated.len(),
+            report.skipped.len(),
+            report.unreadable.len(),
+            report.unsupported.len()
+        );
+        for event in events {
+            self.publish(event);
+        }
+        Ok(report)
     }
 
-    /// Generate thumbnails for all pictures in the library. As of right now,
-    /// this function is very inefficient. For now, clients should use target
-    /// platform thumbnailing, and add those thumbnails manually to each photo.
-    pub fn generate_all_thumbnails(&self) -> Result<(), LumenzaError> {
-        let pictures = self.list_all_pictures()?;
-        for picture in pictures {
-            // Generate thumbnail filename.
-            let tfolder = self.config.get_thumbnails_path();
-            let tpic = PathBuf::from(picture.filename.file_name().unwrap());
-            let tfile = tfolder.join(tpic);
-            picture.generate_thumbnail(&tfile)?;
+    /// Check a file that is already in the library. Files back after going
+    /// missing are reattached, and files whose fingerprint changed get their
+    /// hash and metadata refreshed. Returns the picture if anything changed.
+    fn rescan_picture(
+        &self,
+        id: u32,
+        path: &Path,
+        fingerprint: Option<hash::Fingerprint>,
+        missing: bool,
+    ) -> Result<Option<Picture>, LumenzaError> {
+        let current = hash::fingerprint(path);
+        if current == fingerprint && !missing {
+            return Ok(None);
         }
-        Ok(())
+        let mut picture = match self.database.get_picture(id)? {
+            Some(picture) => picture,
+            None => return Ok(None),
+        };
+        if missing {
+            self.database.set_picture_missing(id, false)?;
+            picture.missing = false;
+        }
+        if current != fingerprint {
+            let hash = hash::hash_file(path)?;
+            // Pictures added before fingerprints were stored are only
+            // refreshed if their content changed.
+            if fingerprint.is_some() || picture.hash.as_ref() != Some(&hash) {
+                self.database.set_picture_hash(id, &hash)?;
+                picture.hash = Some(hash);
+                picture.write_metadata(self)?;
+            }
+            self.database.set_picture_fingerprint(id, current)?;
+        }
+        Ok(Some(picture))
+    }
+
+    /// Generate thumbnails for all pictures in the library. Thumbnails are
+    /// stored in the thumbnail folder under the hash of the picture's
+    /// content, so pictures with the same name never overwrite each other's
+    /// thumbnail. Thumbnails that are up to date are kept, and thumbnails no
+    /// longer used by any picture are deleted. Thumbnails set by the client
+    /// outside of the thumbnail folder are left alone. Clients can still use
+    /// target platform thumbnailing, and add those thumbnails manually to
+    /// each photo. See generate_thumbnails() for progress and cancellation.
+    pub fn generate_all_thumbnails(&self) -> Result<ThumbnailReport, LumenzaError> {
+        self.generate_thumbnails(|_, _| (), &CancelToken::new())
+    }
+
+    /// Same as generate_all_thumbnails(), spreading the work over all CPU
+    /// cores. `progress` is called on the calling thread after each picture
+    /// that needed a thumbnail, with the number of pictures done so far and
+    /// the total. Pictures that fail are listed in the report instead of
+    /// stopping the others.
+    pub fn generate_thumbnails<F>(
+        &self,
+        mut progress: F,
+        cancel: &CancelToken,
+    ) -> Result<ThumbnailReport, LumenzaError>
+    where
+        F: FnMut(usize, usize),
+    {
+        let folder = self.config.get_thumbnails_path();
+        let profiles = self.config.get_thumbnail_profiles();
+        let mut report = ThumbnailReport::default();
+        let mut pending = Vec::new();
+        for picture in self.list_all_pictures()? {
+            let custom = !picture.thumbnail.as_os_str().is_empty()
+                && !picture.thumbnail.starts_with(&folder);
+            if (custom && picture.thumbnail.is_file())
+                || thumbnails::is_current(&picture, &folder, &profiles)
+            {
+                report.skipped += 1;
+            } else {
+                pending.push(picture);
+            }
+        }
+
+        // The database can only be used from this thread, so workers send
+        // their results back here.
+        let total = pending.len();
+        let next = AtomicUsize::new(0);
+        let workers = thread::available_parallelism()
+            .map(|count| count.get())
+            .unwrap_or(1)
+            .min(total);
+        let (sender, receiver) = mpsc::channel();
+        thread::scope(|scope| -> Result<(), LumenzaError> {
+            for _ in 0..workers {
+                let sender = sender.clone();
+                let (pending, next) = (&pending, &next);
+                let (folder, profiles) = (&folder, &profiles);
+                let thumbnailer = self.thumbnailer.as_ref();
+                scope.spawn(move || {
+                    while !cancel.is_cancelled() {
+                        let picture = match pending.get(next.fetch_add(1, Ordering::Relaxed)) {
+                            Some(picture) => picture,
+                            None => break,
+                        };
+                        let result = thumbnails::update(picture, folder, profiles, thumbnailer);
+                        if sender.send((picture, result)).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(sender);
+
+            for (done, (picture, result)) in receiver.iter().enumerate() {
+                match result {
+                    Ok((hash, generated)) => {
+                        if picture.hash.as_ref() != Some(&hash) {
+                            self.database.set_picture_hash(picture.id, &hash)?;
+                        }
+                        // The first profile gives the default thumbnail.
+                        let (_, _, thumbnail) = &generated[0];
+                        if &picture.thumbnail != thumbnail {
+                            self.database.set_picture_thumbnail(picture.id, thumbnail)?;
+                        }
+                        self.database.write_thumbnails(picture.id, &generated)?;
+                        report.generated += 1;
+                        self.publish(LibraryEvent::ThumbnailReady(Picture {
+                            thumbnail: thumbnail.clone(),
+                            ..picture.clone()
+                        }));
+                    }
+                    Err(err) => report.failed.push((picture.clone(), err)),
+                }
+                progress(done + 1, total);
+            }
+            Ok(())
+        })?;
+        report.cancelled = report.generated + report.failed.len() < total;
+
+        self.remove_orphaned_thumbnails()?;
+        Ok(report)
+    }
+
+    /// Generate thumbnails with another Thumbnailer than the default
+    /// ImageThumbnailer, like the thumbnailing library of the platform.
+    /// Thumbnails that are already up to date are kept.
+    pub fn set_thumbnailer(&mut self, thumbnailer: impl Thumbnailer + 'static) {
+        self.thumbnailer = Box::new(thumbnailer);
+    }
+
+    /// Get the sizes and formats of thumbnails generated for each picture.
+    /// The first one gives Picture::get_thumbnail(), use
+    /// Picture::thumbnail_for() to pick another.
+    pub fn get_thumbnail_profiles(&self) -> Vec<ThumbnailProfile> {
+        self.config.get_thumbnail_profiles()
     }
 
-    /// Returns a vector of all pictures in the library. Implicitly makes sure
-    /// that all media registered in the database has a corresponding file.
-    /// If the file is missing/moved, the picture will be removed from the 
-    /// database. You will need to run process_folder() or add_picture() to add 
-    /// that picture again. If you know in advance where the picture was moved,
-    /// you can avoid this by using set_filename() on the Picture struct.
+    /// Set the sizes and formats of thumbnails generated for each picture,
+    /// for instance a small one for a grid and a large one for previews. At
+    /// least one profile is needed, and profiles of the same size must crop
+    /// differently. The quality
+    /// of a profile only applies to JPEG, as PNG and WebP thumbnails are
+    /// written lossless. Thumbnails are only generated by the next call to
+    /// generate_all_thumbnails(), which also deletes the ones of profiles no
+    /// longer used.
+    pub fn set_thumbnail_profiles(
+        &mut self,
+        profiles: &[ThumbnailProfile],
+    ) -> Result<(), LumenzaError> {
+        let mut keys: Vec<(u32, i64)> = profiles
+            .iter()
+            .map(|profile| (profile.size, profile.crop.to_i64()))
+            .collect();
+        keys.sort();
+        keys.dedup();
+        if profiles.is_empty()
+            || keys.len() != profiles.len()
+            || !profiles.iter().all(|profile| profile.is_valid())
+        {
+            return Err(LumenzaError::InvalidThumbnailProfile());
+        }
+        self.config.set_thumbnail_profiles(profiles)
+    }
+
+    /// Delete the thumbnails of the thumbnail folder that no picture uses
+    /// anymore.
+    fn remove_orphaned_thumbnails(&self) -> Result<usize, LumenzaError> {
+        let keep = self.database.list_thumbnails()?.into_iter().collect();
+        thumbnails::remove_orphans(&self.config.get_thumbnails_path(), &keep)
+    }
+
+    /// Returns a vector of all pictures in the library. Pictures marked as
+    /// missing by verify() are left out, see list_missing(). Pictures that
+    /// were moved or renamed within the library's folders are found again by
+    /// process_folder(), which keeps their tags and albums. If you know in
+    /// advance where the picture was moved, you can also use set_filename()
+    /// on the Picture struct.
+    /// Videos are not included, see list_all_media(). Every picture of a
+    /// stack is included, see list_stack_tops().
     pub fn list_all_pictures(&self) -> Result<Vec<Picture>, LumenzaError> {
-        // TODO: Filter out pictures that don't have a corresponding file.
-        self.database.list_all_pictures()
+        self.database.list_all_pictures(Some(MediaKind::Picture))
+    }
+
+    /// Like list_all_pictures(), but only the top of each stack is returned,
+    /// along with the pictures that are not stacked. Use Filter::StackTops
+    /// to do the same in queries.
+    pub fn list_stack_tops(&self) -> Result<Vec<Picture>, LumenzaError> {
+        let filter = Filter::Kind(MediaKind::Picture) & Filter::StackTops & !Filter::Missing;
+        self.query(&Query::new().filter(filter))
+    }
+
+    /// Get the video part of a Live Photo or Android motion photo. Returns
+    /// None for other pictures, and for Live Photos whose video is not in
+    /// the library (yet). Pairs are made when pictures are added.
+    pub fn get_live_video(&self, picture: &Picture) -> Result<Option<LiveVideo>, LumenzaError> {
+        self.database.get_live_video(picture.id)
+    }
+
+    /// Get the still a Live Photo video is paired with, if any.
+    pub fn get_live_photo(&self, video: &Picture) -> Result<Option<Picture>, LumenzaError> {
+        self.database.get_live_still(video.id)
+    }
+
+    /// Returns a vector of all videos in the library, except missing ones.
+    pub fn list_all_videos(&self) -> Result<Vec<Picture>, LumenzaError> {
+        self.database.list_all_pictures(Some(MediaKind::Video))
+    }
+
+    /// Returns a vector of everything in the library, pictures and videos,
+    /// except missing ones. Videos come with their container metadata.
+    pub fn list_all_media(&self) -> Result<Vec<Media>, LumenzaError> {
+        self.database.list_all_media()
+    }
+
+    /// Get a picture or video by its id, along with its container metadata
+    /// if it is a video.
+    pub fn get_media(&self, id: u32) -> Result<Media, LumenzaError> {
+        let picture = self.get_picture(id)?;
+        match self.get_video_info(&picture)? {
+            Some(info) => Ok(Media::Video(picture, info)),
+            None if picture.kind == MediaKind::Video => {
+                Ok(Media::Video(picture, VideoInfo::default()))
+            }
+            None => Ok(Media::Picture(picture)),
+        }
+    }
+
+    /// Get the container metadata of a video. Returns None for pictures.
+    pub fn get_video_info(&self, video: &Picture) -> Result<Option<VideoInfo>, LumenzaError> {
+        if video.kind != MediaKind::Video {
+            return Ok(None);
+        }
+        self.database.get_video_info(video.id)
+    }
+
+    /// Check that the file of every picture and video still exists. Pictures
+    /// whose file is gone are marked as missing rather than removed, so that
+    /// their metadata, tags and albums survive an unplugged external drive.
+    /// Missing pictures whose file is back are reattached.
+    pub fn verify(&self) -> Result<VerifyReport, LumenzaError> {
+        let mut report = VerifyReport::default();
+        for mut picture in self.database.list_missing_pictures()? {
+            if picture.filename.exists() {
+                self.database.set_picture_missing(picture.id, false)?;
+                picture.missing = false;
+                self.publish(LibraryEvent::PictureAdded(picture.clone()));
+                report.reattached.push(picture);
+            } else {
+                report.missing.push(picture);
+            }
+        }
+        for mut picture in self.database.list_all_pictures(None)? {
+            if !picture.filename.exists() {
+                self.database.set_picture_missing(picture.id, true)?;
+                picture.missing = true;
+                self.publish(LibraryEvent::PictureRemoved(picture.clone()));
+                report.missing.push(picture);
+            }
+        }
+        report.missing.sort_by_key(|picture| picture.id);
+        Ok(report)
+    }
+
+    /// Returns the pictures and videos marked as missing by verify().
+    pub fn list_missing(&self) -> Result<Vec<Picture>, LumenzaError> {
+        self.database.list_missing_pictures()
+    }
+
+    /// Remove the pictures marked as missing from the library, along with
+    /// their metadata, tags, album entries and thumbnails. Returns the number
+    /// of pictures removed. Call verify() first to find missing pictures.
+    pub fn purge_missing(&self) -> Result<usize, LumenzaError> {
+        let missing = self.database.list_missing_pictures()?;
+        let deletion = self.database.purge_missing_pictures()?;
+        self.remove_orphaned_thumbnails()?;
+        for picture in missing {
+            self.publish(LibraryEvent::PictureDeleted(picture));
+        }
+        self.publish_deletion(&deletion);
+        Ok(deletion.count)
     }
 
     /// This function is a bit of a one-off, as it will not add the folder
     /// the picture is in. It will only add the picture itself. This function is
     /// intended for callers that want to implement lazy loading of pictures. Use
     /// process_folder() when finished with adding all pictures manually.
-    pub fn add_picture(&self, filename: &PathBuf) -> Result<Picture, LumenzaError> {
-        Picture::new(self, &filename)
+    pub fn add_picture(&self, filename: &Path) -> Result<Picture, LumenzaError> {
+        let picture = Picture::new(self, filename)?;
+        self.publish(LibraryEvent::PictureAdded(picture.clone()));
+        Ok(picture)
+    }
+
+    /// Get a picture or video by its id.
+    pub fn get_picture(&self, id: u32) -> Result<Picture, LumenzaError> {
+        self.database
+            .get_picture(id)?
+            .ok_or(LumenzaError::PictureNotFound())
+    }
+
+    /// Save the changes made to a picture with set_filename() or
+    /// set_thumbnail(). Fails if another picture of the library already has
+    /// the same filename.
+    pub fn update_picture(&self, picture: &Picture) -> Result<(), LumenzaError> {
+        let saved = self
+            .database
+            .get_picture(picture.id)?
+            .ok_or(LumenzaError::PictureNotFound())?;
+        if saved.filename != picture.filename && self.database.lookup_picture(picture)? {
+            return Err(LumenzaError::PictureAlreadyInLibrary());
+        }
+        self.database.update_picture(picture)?;
+        if saved.filename != picture.filename {
+            self.publish(LibraryEvent::PictureMoved {
+                from: saved.filename,
+                picture: picture.clone(),
+            });
+        }
+        if saved.thumbnail != picture.thumbnail {
+            self.publish(LibraryEvent::ThumbnailReady(picture.clone()));
+        }
+        Ok(())
+    }
+
+    /// Find pictures with identical content, for instance copies of the same
+    /// photo synced from different devices. Returns groups of at least two
+    /// pictures, each group sharing the same content. Missing pictures are
+    /// left out. Pictures added before content hashes were stored are hashed
+    /// first.
+    pub fn find_duplicates(&self) -> Result<Vec<Vec<Picture>>, LumenzaError> {
+        for picture in self.database.list_unhashed_pictures()? {
+            // Missing files can't be hashed, and can't be duplicates either.
+            if let Ok(hash) = hash::hash_file(&picture.filename) {
+                self.database.set_picture_hash(picture.id, &hash)?;
+            }
+        }
+
+        let mut groups: Vec<Vec<Picture>> = Vec::new();
+        for picture in self.database.list_duplicate_pictures()? {
+            match groups.last_mut() {
+                Some(group) if group[0].hash == picture.hash => group.push(picture),
+                _ => groups.push(vec![picture]),
+            }
+        }
+        Ok(groups)
+    }
+
+    /// Get the capture metadata of a picture, as read when it was added to
+    /// the library. Pictures without metadata give empty metadata.
+    pub fn get_metadata(&self, picture: &Picture) -> Result<Metadata, LumenzaError> {
+        if self.database.get_picture(picture.id)?.is_none() {
+            return Err(LumenzaError::PictureNotFound());
+        }
+        Ok(self.database.get_metadata(picture.id)?.unwrap_or_default())
+    }
+
+    /// Read the metadata of a picture from its file again. Useful after the
+    /// file was edited outside of Lumenza.
+    pub fn refresh_metadata(&self, picture: &Picture) -> Result<Metadata, LumenzaError> {
+        if self.database.get_picture(picture.id)?.is_none() {
+            return Err(LumenzaError::PictureNotFound());
+        }
+        let metadata = metadata::read(&picture.filename);
+        self.database.write_metadata(picture.id, &metadata)?;
+        self.publish(LibraryEvent::MetadataChanged(picture.clone()));
+        Ok(metadata)
+    }
+
+    /// Returns the pictures matching the query, sorted and paginated as
+    /// requested. See the filter module for building queries.
+    pub fn query(&self, query: &Query) -> Result<Vec<Picture>, LumenzaError> {
+        self.database.query_pictures(query)
+    }
+
+    /// Count the pictures matching the filter, for instance to know how many
+    /// pages of results a query has.
+    pub fn count(&self, filter: &Filter) -> Result<u32, LumenzaError> {
+        self.database.count_pictures(filter)
+    }
+
+    /// Get the rating of a picture, from 0 (unrated) to 5.
+    pub fn get_rating(&self, picture: &Picture) -> Result<u8, LumenzaError> {
+        self.database
+            .get_rating(picture.id)?
+            .ok_or(LumenzaError::PictureNotFound())
+    }
+
+    /// Rate a picture from 0 (unrated) to 5.
+    pub fn set_rating(&self, picture: &Picture, rating: u8) -> Result<(), LumenzaError> {
+        if rating > 5 {
+            return Err(LumenzaError::InvalidRating());
+        }
+        self.database.set_rating(picture.id, rating)?;
+        self.publish(LibraryEvent::MetadataChanged(picture.clone()));
+        Ok(())
+    }
+
+    /// Look for a picture of the library with the same content and size as
+    /// the given one, but whose file no longer exists. That is the picture
+    /// that was moved or renamed. When several identical copies are gone, the
+    /// one with the same modification time wins, as moving a file keeps it.
+    fn find_moved_picture(&self, picture: &Picture) -> Result<Option<Picture>, LumenzaError> {
+        let hash = match &picture.hash {
+            Some(hash) => hash,
+            None => return Ok(None),
+        };
+        let current = hash::fingerprint(&picture.filename);
+        let mut found = None;
+        for candidate in self.database.list_pictures_by_hash(hash)? {
+            if candidate.filename.exists() {
+                continue;
+            }
+            // Pictures added before fingerprints were stored only have their
+            // hash to go by.
+            let stored = self.database.get_picture_fingerprint(candidate.id)?;
+            if let (Some((size, mtime)), Some((current_size, current_mtime))) = (stored, current) {
+                if size != current_size {
+                    continue;
+                }
+                if mtime == current_mtime {
+                    return Ok(Some(candidate));
+                }
+            }
+            found.get_or_insert(candidate);
+        }
+        Ok(found)
     }
 
     /// Get a vector of all multimedia folders.
     pub fn list_all_folders(&self) -> Vec<PathBuf> {
         self.config.get_folders_paths()
     }
+
+    /// Remove a folder from the library. Its pictures and videos are deleted
+    /// from the library, along with their metadata, tags, album entries and
+    /// thumbnails. If `keep_metadata` is set, they are detached instead:
+    /// hidden like missing pictures, but left alone by verify() and
+    /// purge_missing(), so that adding the folder again with process_folder()
+    /// finds them as they were. Pictures that are also inside another folder
+    /// of the library are left alone, and files on disk are never touched.
+    pub fn remove_folder(
+        &mut self,
+        folder: &Path,
+        keep_metadata: bool,
+    ) -> Result<RemovalReport, LumenzaError> {
+        let full_path = folder.absolutize().unwrap_or_default().into_owned();
+        let mut folders = self.config.get_folders_paths();
+        if !folders.contains(&full_path) {
+            return Err(LumenzaError::FolderNotFound());
+        }
+        folders.retain(|other| other != &full_path);
+        let pictures: Vec<Picture> = self
+            .query(&Query::new().filter(Filter::folder(&full_path)))?
+            .into_iter()
+            .filter(|picture| {
+                !folders
+                    .iter()
+                    .any(|other| picture.filename.starts_with(other))
+            })
+            .collect();
+
+        let mut report = RemovalReport::default();
+        if keep_metadata {
+            for mut picture in pictures {
+                self.database.detach_picture(picture.id)?;
+                if !picture.missing {
+                    picture.missing = true;
+                    self.publish(LibraryEvent::PictureRemoved(picture.clone()));
+                }
+                report.detached.push(picture);
+            }
+        } else {
+            let ids: Vec<u32> = pictures.iter().map(|picture| picture.id).collect();
+            let deletion = self.database.delete_pictures(&ids)?;
+            report.thumbnails = self.remove_orphaned_thumbnails()?;
+            for picture in &pictures {
+                self.publish(LibraryEvent::PictureDeleted(picture.clone()));
+            }
+            self.publish_deletion(&deletion);
+            report.deleted = pictures;
+        }
+        self.config.remove_folder(&full_path)?;
+        log::info!(
+            "Removed {}: {} deleted, {} detached, {} thumbnails deleted",
+            full_path.display(),
+            report.deleted.len(),
+            report.detached.len(),
+            report.thumbnails
+        );
+        Ok(report)
+    }
+}
+
+// Event methods.
+impl Library {
+    /// Get notified of every change made to the library from now on, such
+    /// as pictures added by process_folder() or watch(), thumbnails
+    /// generated, or albums edited. Events are sent once the change is in
+    /// the database, on the thread making it; the receiver can be moved to
+    /// another thread. Dropping the receiver ends the subscription.
+    pub fn subscribe(&self) -> mpsc::Receiver<LibraryEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.borrow_mut().push(sender);
+        receiver
+    }
+
+    /// Send an event to every subscriber, forgetting the ones that are gone.
+    pub(crate) fn publish(&self, event: LibraryEvent) {
+        self.subscribers
+            .borrow_mut()
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+
+    /// Tell subscribers about the albums and stacks changed by deleting
+    /// pictures.
+    fn publish_deletion(&self, deletion: &database::Deletion) {
+        for album in &deletion.albums {
+            self.publish(LibraryEvent::AlbumChanged(*album));
+        }
+        for stack in &deletion.stacks {
+            self.publish(LibraryEvent::StackChanged(*stack));
+        }
+    }
+}
+
+// Watch methods.
+impl Library {
+    /// Watch the library's folders, applying changes to the database as
+    /// they happen: new files are added, deleted files are marked as
+    /// missing, moved or renamed files keep their entry, and modified files
+    /// get their hash and metadata refreshed. `on_event` is called after
+    /// each change, after subscribers are notified. Blocks until `cancel` is
+    /// cancelled, so it is usually run on a thread of its own. A Library
+    /// can't be shared between threads: move it to the watching thread, and
+    /// use another one opened with Library::open() on the same config for
+    /// the rest of the client. Folders added while watching are not watched,
+    /// nor are folders that can't be, like ones on an unplugged drive.
+    pub fn watch<F>(&self, mut on_event: F, cancel: &CancelToken) -> Result<(), LumenzaError>
+    where
+        F: FnMut(WatchEvent),
+    {
+        let folders = self.config.get_folders_paths();
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher =
+            notify::recommended_watcher(sender).map_err(|_| LumenzaError::IoError())?;
+        // Folders that can't be watched, like an unplugged drive, don't stop
+        // the others from being watched.
+        for folder in &folders {
+            if let Err(err) = watcher.watch(folder, RecursiveMode::Recursive) {
+                log::warn!("Can't watch {}: {}", folder.display(), err);
+            }
+        }
+
+        // Paths that changed, and when they last did.
+        let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+        while !cancel.is_cancelled() {
+            match receiver.recv_timeout(WATCH_POLL_INTERVAL) {
+                Ok(Ok(event)) => {
+                    // Reading files, which Lumenza itself does, is not a change.
+                    if let EventKind::Access(access) = event.kind {
+                        if access != AccessKind::Close(AccessMode::Write) {
+                            continue;
+                        }
+                    }
+                    for path in event.paths {
+                        if !is_hidden_path(&folders, &path) {
+                            pending.insert(path, Instant::now());
+                        }
+                    }
+                }
+                Ok(Err(_)) | Err(mpsc::RecvTimeoutError::Timeout) => (),
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+
+            let settled: Vec<PathBuf> = pending
+                .iter()
+                .filter(|(_, changed)| changed.elapsed() >= WATCH_SETTLE_TIME)
+                .map(|(path, _)| path.clone())
+                .collect();
+            for path in &settled {
+                pending.remove(path);
+            }
+            // Files that appeared go first, so that a renamed file is found
+            // again before its old path is seen as deleted.
+            let (present, gone): (Vec<_>, Vec<_>) =
+                settled.into_iter().partition(|path| path.exists());
+            for path in present {
+                let files = WalkDir::new(&path)
+                    .into_iter()
+                    .filter_entry(|e| !is_hidden_folder(e))
+                    .filter_map(|entry| entry.ok())
+                    .filter(|entry| entry.file_type().is_file());
+                for file in files {
+                    if let Some(event) = self.apply_file_change(file.path())? {
+                        self.publish(event.clone().into());
+                        on_event(event);
+                    }
+                }
+            }
+            for path in gone {
+                for event in self.apply_removal(&path)? {
+                    self.publish(event.clone().into());
+                    on_event(event);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Bring the database up to date with a file that was created or
+    /// modified.
+    fn apply_file_change(&self, path: &Path) -> Result<Option<WatchEvent>, LumenzaError> {
+        if MediaKind::from_mime(&media::guess_mime(path)).is_none() {
+            return Ok(None);
+        }
+        if let Some(picture) = self.database.get_picture_by_filename(path)? {
+            let fingerprint = self.database.get_picture_fingerprint(picture.id)?;
+            let updated = match self.rescan_picture(picture.id, path, fingerprint, picture.missing)
+            {
+                Ok(Some(updated)) => updated,
+                Ok(None) => return Ok(None),
+                Err(LumenzaError::DatabaseError(err)) => {
+                    return Err(LumenzaError::DatabaseError(err))
+                }
+                // The file went away in the meantime.
+                Err(_) => return Ok(None),
+            };
+            return Ok(Some(match picture.missing {
+                true => WatchEvent::Added(updated),
+                false => WatchEvent::Modified(updated),
+            }));
+        }
+
+        let picture = match Picture::prepare(self, path) {
+            Ok(picture) => picture,
+            Err(LumenzaError::DatabaseError(err)) => return Err(LumenzaError::DatabaseError(err)),
+            Err(_) => return Ok(None),
+        };
+        match self.find_moved_picture(&picture)? {
+            Some(mut moved) => {
+                self.database
+                    .set_picture_filename(moved.id, &picture.filename)?;
+                self.database
+                    .set_picture_fingerprint(moved.id, hash::fingerprint(&picture.filename))?;
+                let from = std::mem::replace(&mut moved.filename, picture.filename);
+                moved.missing = false;
+                Ok(Some(WatchEvent::Moved {
+                    from,
+                    picture: moved,
+                }))
+            }
+            None => {
+                let picture = picture.insert(self)?;
+                let stacks =
+                    self.write_related_stacks(|group| group.iter().any(|p| p.id == picture.id))?;
+                for stack in stacks {
+                    self.publish(LibraryEvent::StackChanged(stack));
+                }
+                Ok(Some(WatchEvent::Added(picture)))
+            }
+        }
+    }
+
+    /// Mark the pictures of a deleted file or folder as missing.
+    fn apply_removal(&self, path: &Path) -> Result<Vec<WatchEvent>, LumenzaError> {
+        let pictures = match self.database.get_picture_by_filename(path)? {
+            Some(picture) => vec![picture],
+            None => self.query(&Query::new().filter(Filter::folder(path)))?,
+        };
+        let mut events = Vec::new();
+        for mut picture in pictures.into_iter().filter(|picture| !picture.missing) {
+            self.database.set_picture_missing(picture.id, true)?;
+            picture.missing = true;
+            events.push(WatchEvent::Removed(picture));
+        }
+        Ok(events)
+    }
+}
+
+// Album methods.
+impl Library {
+    /// Create a new, empty album. Album names do not have to be unique.
+    pub fn create_album(&self, name: &str) -> Result<Album, LumenzaError> {
+        let id = self.database.write_album(name)?;
+        self.publish(LibraryEvent::AlbumChanged(id));
+        self.get_album(id)
+    }
+
+    /// Get an album by its id.
+    pub fn get_album(&self, id: u32) -> Result<Album, LumenzaError> {
+        self.database
+            .get_album(id)?
+            .ok_or(LumenzaError::AlbumNotFound())
+    }
+
+    /// Returns all albums, sorted by name, along with their picture counts.
+    pub fn list_albums(&self) -> Result<Vec<Album>, LumenzaError> {
+        self.database.list_albums()
+    }
+
+    /// Rename the album.
+    pub fn rename_album(&self, album: &mut Album, name: &str) -> Result<(), LumenzaError> {
+        self.database.rename_album(album.id, name)?;
+        album.name = name.to_string();
+        self.publish(LibraryEvent::AlbumChanged(album.id));
+        Ok(())
+    }
+
+    /// Delete the album. The pictures in it stay in the library.
+    pub fn delete_album(&self, album: Album) -> Result<(), LumenzaError> {
+        self.database.delete_album(album.id)?;
+        self.publish(LibraryEvent::AlbumChanged(album.id));
+        Ok(())
+    }
+
+    /// Returns the pictures in the album, in album order.
+    pub fn list_album_pictures(&self, album: &Album) -> Result<Vec<Picture>, LumenzaError> {
+        self.database.list_album_pictures(album.id)
+    }
+
+    /// Append a picture to the end of the album.
+    pub fn add_to_album(&self, album: &mut Album, picture: &Picture) -> Result<(), LumenzaError> {
+        if self.database.get_picture(picture.id)?.is_none() {
+            return Err(LumenzaError::PictureNotFound());
+        }
+        self.database.add_album_picture(album.id, picture.id)?;
+        *album = self.get_album(album.id)?;
+        self.publish(LibraryEvent::AlbumChanged(album.id));
+        Ok(())
+    }
+
+    /// Remove a picture from the album. If it was the album cover, the album
+    /// will no longer have an explicit cover.
+    pub fn remove_from_album(
+        &self,
+        album: &mut Album,
+        picture: &Picture,
+    ) -> Result<(), LumenzaError> {
+        self.database.remove_album_picture(album.id, picture.id)?;
+        *album = self.get_album(album.id)?;
+        self.publish(LibraryEvent::AlbumChanged(album.id));
+        Ok(())
+    }
+
+    /// Move a picture to a new zero-based position in the album, shifting the
+    /// pictures in between. Positions past the end move it to the end.
+    pub fn move_in_album(
+        &self,
+        album: &Album,
+        picture: &Picture,
+        position: usize,
+    ) -> Result<(), LumenzaError> {
+        self.database
+            .move_album_picture(album.id, picture.id, position)?;
+        self.publish(LibraryEvent::AlbumChanged(album.id));
+        Ok(())
+    }
+
+    /// Choose the picture used as the album cover. The picture must already
+    /// be in the album. Passing None resets the album to the default cover.
+    pub fn set_album_cover(
+        &self,
+        album: &mut Album,
+        picture: Option<&Picture>,
+    ) -> Result<(), LumenzaError> {
+        let cover = picture.map(|picture| picture.id);
+        if let Some(id) = cover {
+            if !self
+                .database
+                .list_album_picture_ids(album.id)?
+                .contains(&id)
+            {
+                return Err(LumenzaError::PictureNotInAlbum());
+            }
+        }
+        self.database.set_album_cover(album.id, cover)?;
+        album.cover = cover;
+        self.publish(LibraryEvent::AlbumChanged(album.id));
+        Ok(())
+    }
+
+    /// Get the cover of the album. This is the picture chosen with
+    /// set_album_cover(), or the first picture of the album otherwise. Empty
+    /// albums do not have a cover.
+    pub fn get_album_cover(&self, album: &Album) -> Result<Option<Picture>, LumenzaError> {
+        let id = match album.cover {
+            Some(id) => Some(id),
+            None => self
+                .database
+                .list_album_picture_ids(album.id)?
+                .first()
+                .copied(),
+        };
+        match id {
+            Some(id) => self.database.get_picture(id),
+            None => Ok(None),
+        }
+    }
+}
+
+// Stack methods.
+impl Library {
+    /// Stack pictures together, with `top` standing for the others. The
+    /// pictures must not be in a stack already.
+    pub fn create_stack(&self, pictures: &[Picture], top: &Picture) -> Result<Stack, LumenzaError> {
+        let mut ids: Vec<u32> = pictures.iter().map(|picture| picture.id).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        if ids.len() < 2 || !ids.contains(&top.id) {
+            return Err(LumenzaError::InvalidStack());
+        }
+        for id in &ids {
+            if self.database.get_picture(*id)?.is_none() {
+                return Err(LumenzaError::PictureNotFound());
+            }
+        }
+        let id = self.database.write_stack(StackKind::Manual, top.id, &ids)?;
+        self.publish(LibraryEvent::StackChanged(id));
+        self.get_stack(id)
+    }
+
+    /// Get a stack by its id.
+    pub fn get_stack(&self, id: u32) -> Result<Stack, LumenzaError> {
+        self.database
+            .get_stack(id)?
+            .ok_or(LumenzaError::StackNotFound())
+    }
+
+    /// Get the stack the picture is in, if any.
+    pub fn get_picture_stack(&self, picture: &Picture) -> Result<Option<Stack>, LumenzaError> {
+        self.database.get_picture_stack(picture.id)
+    }
+
+    /// List every stack, in creation order.
+    pub fn list_stacks(&self) -> Result<Vec<Stack>, LumenzaError> {
+        self.database.list_stacks()
+    }
+
+    /// List the pictures of the stack, its top first.
+    pub fn list_stack_pictures(&self, stack: &Stack) -> Result<Vec<Picture>, LumenzaError> {
+        self.database.list_stack_pictures(stack.id)
+    }
+
+    /// Choose the picture standing for the others of the stack.
+    pub fn set_stack_top(&self, stack: &mut Stack, picture: &Picture) -> Result<(), LumenzaError> {
+        self.database.set_stack_top(stack.id, picture.id)?;
+        stack.top = picture.id;
+        self.publish(LibraryEvent::StackChanged(stack.id));
+        Ok(())
+    }
+
+    /// Take a picture out of the stack. If it was the top, the first
+    /// remaining picture takes its place. A stack left with a single picture
+    /// is deleted, and its picture count drops to zero.
+    pub fn remove_from_stack(
+        &self,
+        stack: &mut Stack,
+        picture: &Picture,
+    ) -> Result<(), LumenzaError> {
+        if self.database.remove_stack_picture(stack.id, picture.id)? {
+            stack.picture_count = 0;
+        } else {
+            *stack = self.get_stack(stack.id)?;
+        }
+        self.publish(LibraryEvent::StackChanged(stack.id));
+        Ok(())
+    }
+
+    /// Delete the stack. Its pictures are left in the library, unstacked.
+    pub fn delete_stack(&self, stack: Stack) -> Result<(), LumenzaError> {
+        self.database.delete_stack(stack.id)?;
+        self.publish(LibraryEvent::StackChanged(stack.id));
+        Ok(())
+    }
+
+    /// Stack the pictures of the library that are related to each other:
+    /// files with the same name in the same folder, like RAW+JPEG pairs,
+    /// edited copies next to their original, and bursts of pictures taken
+    /// within a second of each other. Pictures that are already stacked are
+    /// left alone. Returns the number of stacks created.
+    pub fn stack_related_pictures(&self) -> Result<usize, LumenzaError> {
+        let stacks = self.write_related_stacks(|_| true)?;
+        for stack in &stacks {
+            self.publish(LibraryEvent::StackChanged(*stack));
+        }
+        Ok(stacks.len())
+    }
+
+    /// Stack the related pictures of the groups accepted by `keep`. Returns
+    /// the ids of the stacks created, for the caller to publish.
+    fn write_related_stacks<F>(&self, keep: F) -> Result<Vec<u32>, LumenzaError>
+    where
+        F: Fn(&[Picture]) -> bool,
+    {
+        let groups = stack::group_related(self.database.list_unstacked_pictures()?);
+        let mut stacks = Vec::new();
+        for (kind, pictures) in groups.iter().filter(|(_, pictures)| keep(pictures)) {
+            let ids: Vec<u32> = pictures.iter().map(|picture| picture.id).collect();
+            stacks.push(self.database.write_stack(*kind, ids[0], &ids)?);
+        }
+        Ok(stacks)
+    }
+}
+
+// Tag methods.
+impl Library {
+    /// Create a tag from its full path, such as `Places/Italy/Rome`. Missing
+    /// parent tags are created along the way. If the tag already exists, it
+    /// is returned as is.
+    pub fn create_tag(&self, path: &str) -> Result<Tag, LumenzaError> {
+        let names = tag::split_path(path).ok_or(LumenzaError::InvalidTagName())?;
+
+        let mut parent = None;
+        for name in names {
+            let id = match self.database.find_tag(parent, name)? {
+                Some(id) => id,
+                None => {
+                    let id = self.database.write_tag(parent, name)?;
+                    self.publish(LibraryEvent::TagChanged(id));
+                    id
+                }
+            };
+            parent = Some(id);
+        }
+        // There is always at least one name, so the parent is set.
+        self.get_tag(parent.unwrap())
+    }
+
+    /// Get a tag by its id.
+    pub fn get_tag(&self, id: u32) -> Result<Tag, LumenzaError> {
+        self.database
+            .get_tag(id)?
+            .ok_or(LumenzaError::TagNotFound())
+    }
+
+    /// Look up a tag by its full path. Returns None if it does not exist.
+    pub fn find_tag(&self, path: &str) -> Result<Option<Tag>, LumenzaError> {
+        let names = tag::split_path(path).ok_or(LumenzaError::InvalidTagName())?;
+
+        let mut parent = None;
+        for name in names {
+            match self.database.find_tag(parent, name)? {
+                Some(id) => parent = Some(id),
+                None => return Ok(None),
+            }
+        }
+        match parent {
+            Some(id) => self.database.get_tag(id),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns all tags, sorted by path so that children follow their parent.
+    pub fn list_tags(&self) -> Result<Vec<Tag>, LumenzaError> {
+        self.database.list_tags()
+    }
+
+    /// Rename a single level of the tag tree. Descendants follow along, so
+    /// renaming `Italy` in `Places/Italy/Rome` gives `Places/Italia/Rome`. Use
+    /// merge_tags() if a tag with the new name already exists.
+    pub fn rename_tag(&self, tag: &mut Tag, name: &str) -> Result<(), LumenzaError> {
+        let name = name.trim();
+        if !tag::is_valid_name(name) {
+            return Err(LumenzaError::InvalidTagName());
+        }
+        if let Some(id) = self.database.find_tag(tag.parent, name)? {
+            if id != tag.id {
+                return Err(LumenzaError::TagAlreadyExists());
+            }
+        }
+        self.database.rename_tag(tag.id, name)?;
+        *tag = self.get_tag(tag.id)?;
+        self.publish(LibraryEvent::TagChanged(tag.id));
+        Ok(())
+    }
+
+    /// Merge `source` into `target`. Every picture tagged with `source` will
+    /// be tagged with `target`, and the children of `source` are moved under
+    /// `target` (merging children with the same name). `source` is deleted.
+    pub fn merge_tags(&self, source: Tag, target: &Tag) -> Result<(), LumenzaError> {
+        // A tag can't be merged into itself or one of its own descendants.
+        if self
+            .database
+            .list_tag_subtree(source.id)?
+            .contains(&target.id)
+        {
+            return Err(LumenzaError::InvalidTagMerge());
+        }
+        let pictures = self.database.list_tagged_pictures(source.id, true)?;
+        self.database.merge_tag(source.id, target.id)?;
+        self.publish(LibraryEvent::TagChanged(source.id));
+        self.publish(LibraryEvent::TagChanged(target.id));
+        for picture in pictures {
+            self.publish(LibraryEvent::MetadataChanged(picture));
+        }
+        Ok(())
+    }
+
+    /// Delete the tag, along with all of its descendants. Pictures stay in
+    /// the library.
+    pub fn delete_tag(&self, tag: Tag) -> Result<(), LumenzaError> {
+        let pictures = self.database.list_tagged_pictures(tag.id, true)?;
+        self.database.delete_tag(tag.id)?;
+        self.publish(LibraryEvent::TagChanged(tag.id));
+        for picture in pictures {
+            self.publish(LibraryEvent::MetadataChanged(picture));
+        }
+        Ok(())
+    }
+
+    /// Assign a tag to a picture. Assigning the same tag twice has no effect.
+    pub fn tag_picture(&self, picture: &Picture, tag: &Tag) -> Result<(), LumenzaError> {
+        let tagged = self
+            .database
+            .get_picture(picture.id)?
+            .ok_or(LumenzaError::PictureNotFound())?;
+        if self.database.get_tag(tag.id)?.is_none() {
+            return Err(LumenzaError::TagNotFound());
+        }
+        self.database.tag_picture(picture.id, tag.id)?;
+        self.publish(LibraryEvent::MetadataChanged(tagged));
+        Ok(())
+    }
+
+    /// Remove a tag from a picture. Tags of the same branch are left as is.
+    pub fn untag_picture(&self, picture: &Picture, tag: &Tag) -> Result<(), LumenzaError> {
+        self.database.untag_picture(picture.id, tag.id)?;
+        if let Some(untagged) = self.database.get_picture(picture.id)? {
+            self.publish(LibraryEvent::MetadataChanged(untagged));
+        }
+        Ok(())
+    }
+
+    /// Returns the tags assigned to a picture.
+    pub fn list_picture_tags(&self, picture: &Picture) -> Result<Vec<Tag>, LumenzaError> {
+        self.database.list_picture_tags(picture.id)
+    }
+
+    /// Returns the pictures tagged with the given tag. If `descendants` is
+    /// set, pictures tagged with any tag below it are included as well, so
+    /// listing `Places/Italy` also gives pictures tagged `Places/Italy/Rome`.
+    pub fn list_tagged_pictures(
+        &self,
+        tag: &Tag,
+        descendants: bool,
+    ) -> Result<Vec<Picture>, LumenzaError> {
+        self.database.list_tagged_pictures(tag.id, descendants)
+    }
+}
+
+/// Number of files written in a single transaction while scanning a folder.
+/// Large enough to be fast, small enough not to lose much if a scan fails.
+const SCAN_BATCH_SIZE: usize = 500;
+
+/// Groups the writes of a scan in transactions of SCAN_BATCH_SIZE files.
+struct ScanBatch<'a> {
+    transaction: Option<rusqlite::Transaction<'a>>,
+    written: usize,
+}
+
+impl<'a> ScanBatch<'a> {
+    fn new(library: &'a Library) -> Result<Self, LumenzaError> {
+        Ok(ScanBatch {
+            transaction: Some(library.database.transaction()?),
+            written: 0,
+        })
+    }
+
+    /// Count a file as written, committing the batch once it is full.
+    fn written(&mut self, library: &'a Library) -> Result<(), LumenzaError> {
+        self.written += 1;
+        if self.written.is_multiple_of(SCAN_BATCH_SIZE) {
+            if let Some(full) = self.transaction.take() {
+                full.commit()?;
+            }
+            self.transaction = Some(library.database.transaction()?);
+        }
+        Ok(())
+    }
+
+    fn commit(self) -> Result<(), LumenzaError> {
+        match self.transaction {
+            Some(transaction) => Ok(transaction.commit()?),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Whether the path is in a hidden folder, or is a hidden file, within one
+/// of the library's folders.
+fn is_hidden_path(folders: &[PathBuf], path: &Path) -> bool {
+    let relative = folders
+        .iter()
+        .find_map(|folder| path.strip_prefix(folder).ok())
+        .unwrap_or(path);
+    relative
+        .components()
+        .any(|component| component.as_os_str().to_string_lossy().starts_with('.'))
 }
 
 // Make sure that we don't search for pictures in hidden folders.
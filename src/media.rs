@@ -0,0 +1,313 @@
+use chrono::NaiveDateTime;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use crate::picture::Picture;
+
+/// Kind of multimedia stored in the library
+///
+/// Every file in the library is listed as a Picture, whatever its kind, so
+/// that albums, tags and filters work the same way for all of them. Use
+/// Picture::get_kind() to tell them apart, or Library::list_all_media() to
+/// get videos along with their container metadata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaKind {
+    Picture,
+    Video,
+}
+
+/// Camera RAW formats, by extension. mime_guess doesn't know most of them,
+/// or calls them `application/octet-stream`.
+const RAW_TYPES: &[(&str, &str)] = &[
+    ("3fr", "image/x-hasselblad-3fr"),
+    ("arw", "image/x-sony-arw"),
+    ("cr2", "image/x-canon-cr2"),
+    ("cr3", "image/x-canon-cr3"),
+    ("crw", "image/x-canon-crw"),
+    ("dng", "image/x-adobe-dng"),
+    ("erf", "image/x-epson-erf"),
+    ("iiq", "image/x-phaseone-iiq"),
+    ("mrw", "image/x-minolta-mrw"),
+    ("nef", "image/x-nikon-nef"),
+    ("nrw", "image/x-nikon-nrw"),
+    ("orf", "image/x-olympus-orf"),
+    ("pef", "image/x-pentax-pef"),
+    ("raf", "image/x-fuji-raf"),
+    ("rw2", "image/x-panasonic-rw2"),
+    ("rwl", "image/x-leica-rwl"),
+    ("sr2", "image/x-sony-sr2"),
+    ("srf", "image/x-sony-srf"),
+    ("srw", "image/x-samsung-srw"),
+    ("x3f", "image/x-sigma-x3f"),
+];
+
+/// Number of bytes read from the beginning of a file to detect its type.
+const SNIFF_SIZE: u64 = 32;
+
+/// Detect the mime type of a file from its content, falling back to its
+/// extension for formats that can't be recognized from their first bytes.
+/// Files whose content doesn't match their extension, like a text file named
+/// `.jpg`, give an empty string, as do unknown files.
+pub(crate) fn guess_mime(path: &Path) -> String {
+    let extension = mime_from_extension(path);
+    let mut header = Vec::new();
+    if let Ok(file) = File::open(path) {
+        let _ = file.take(SNIFF_SIZE).read_to_end(&mut header);
+    }
+    match sniff_mime(&header) {
+        Some(mime) if is_refined_by(mime, &extension) => extension,
+        Some(mime) => mime.to_string(),
+        // The content doesn't match the extension.
+        None if is_sniffable_mime(&extension) || is_raw_mime(&extension) => String::new(),
+        None => extension,
+    }
+}
+
+/// Signatures of the formats recognized by sniff_mime(), by offset.
+const SIGNATURES: &[(usize, &[u8], &str)] = &[
+    (0, &[0xff, 0xd8, 0xff], "image/jpeg"),
+    (0, b"\x89PNG\r\n\x1a\n", "image/png"),
+    (0, b"GIF87a", "image/gif"),
+    (0, b"GIF89a", "image/gif"),
+    (0, b"BM", "image/bmp"),
+    (0, b"II*\0", "image/tiff"),
+    (0, b"MM\0*", "image/tiff"),
+    (0, &[0x1a, 0x45, 0xdf, 0xa3], "video/x-matroska"),
+    // Camera RAW formats that are not TIFF files.
+    (0, b"FUJIFILMCCD-RAW", "image/x-fuji-raf"),
+    (0, b"IIRO", "image/x-olympus-orf"),
+    (0, b"IIRS", "image/x-olympus-orf"),
+    (0, b"MMOR", "image/x-olympus-orf"),
+    (0, b"IIU\0", "image/x-panasonic-rw2"),
+    (0, b"\0MRM", "image/x-minolta-mrw"),
+    (0, b"FOVb", "image/x-sigma-x3f"),
+    (6, b"HEAPCCDR", "image/x-canon-crw"),
+];
+
+/// Brands of ISO base media files (MP4, QuickTime, HEIF), found after
+/// `ftyp` at the beginning of the file. Other brands are MP4 videos, unless
+/// the extension says the file is audio.
+const BRANDS: &[(&[u8], &str)] = &[
+    (b"qt  ", "video/quicktime"),
+    (b"avif", "image/avif"),
+    (b"avis", "image/avif"),
+    (b"heic", "image/heic"),
+    (b"heix", "image/heic"),
+    (b"heim", "image/heic"),
+    (b"heis", "image/heic"),
+    (b"hevc", "image/heic-sequence"),
+    (b"hevx", "image/heic-sequence"),
+    (b"mif1", "image/heif"),
+    (b"msf1", "image/heif-sequence"),
+    (b"crx ", "image/x-canon-cr3"),
+    // iTunes audio and audiobooks, which are not media of the library.
+    (b"M4A ", "audio/mp4"),
+    (b"M4B ", "audio/mp4"),
+    (b"M4P ", "audio/mp4"),
+];
+
+/// Detect the type of a file from its first bytes.
+fn sniff_mime(header: &[u8]) -> Option<&'static str> {
+    let found = SIGNATURES.iter().find(|(offset, signature, _)| {
+        header
+            .get(*offset..)
+            .is_some_and(|h| h.starts_with(signature))
+    });
+    if let Some((_, _, mime)) = found {
+        return Some(mime);
+    }
+    if header.starts_with(b"RIFF") && header.get(8..12) == Some(b"WEBP") {
+        return Some("image/webp");
+    }
+    if header.get(4..8) == Some(b"ftyp") {
+        let brand = header.get(8..12)?;
+        let mime = BRANDS
+            .iter()
+            .find(|(known, _)| *known == brand)
+            .map(|(_, mime)| *mime);
+        return Some(mime.unwrap_or("video/mp4"));
+    }
+    None
+}
+
+/// Whether the extension of a file tells more about its type than its
+/// content, like for the many RAW formats that are TIFF files.
+fn is_refined_by(sniffed: &str, extension: &str) -> bool {
+    match sniffed {
+        "image/tiff" => is_raw_mime(extension),
+        "video/x-matroska" => extension == "video/webm",
+        "video/mp4" => extension.starts_with("video/") || extension.starts_with("audio/"),
+        _ => is_raw_mime(sniffed) && is_raw_mime(extension),
+    }
+}
+
+/// Whether files of this type are always recognized by sniff_mime().
+fn is_sniffable_mime(mime: &str) -> bool {
+    matches!(
+        mime,
+        "image/jpeg"
+            | "image/png"
+            | "image/gif"
+            | "image/bmp"
+            | "image/tiff"
+            | "image/webp"
+            | "image/avif"
+            | "image/heic"
+            | "image/heif"
+            | "video/mp4"
+            | "video/quicktime"
+            | "video/x-matroska"
+            | "video/webm"
+    )
+}
+
+/// Guess the mime type of a file from its extension, including camera RAW
+/// formats. Unknown files give an empty string.
+pub(crate) fn mime_from_extension(path: &Path) -> String {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+    let raw = RAW_TYPES
+        .iter()
+        .find(|(raw, _)| Some(*raw) == extension.as_deref());
+    match raw {
+        Some((_, mime)) => mime.to_string(),
+        None => mime_guess::from_path(path)
+            .first_raw()
+            .unwrap_or_default()
+            .to_string(),
+    }
+}
+
+/// Whether the mime type is one of a camera RAW format.
+pub(crate) fn is_raw_mime(mime: &str) -> bool {
+    RAW_TYPES.iter().any(|(_, raw)| *raw == mime)
+}
+
+// Static methods
+impl MediaKind {
+    /// Guess the kind of a file from its mime type. Returns None for files
+    /// that are not multimedia.
+    pub(crate) fn from_mime(mime: &str) -> Option<Self> {
+        if mime.starts_with("image/") {
+            Some(MediaKind::Picture)
+        } else if mime.starts_with("video/") {
+            Some(MediaKind::Video)
+        } else {
+            None
+        }
+    }
+    pub(crate) fn from_i64(kind: i64) -> Self {
+        match kind {
+            1 => MediaKind::Video,
+            _ => MediaKind::Picture,
+        }
+    }
+}
+
+// Instance methods
+impl MediaKind {
+    pub(crate) fn to_i64(self) -> i64 {
+        match self {
+            MediaKind::Picture => 0,
+            MediaKind::Video => 1,
+        }
+    }
+}
+
+/// Container metadata of a video
+///
+/// Read from the MP4/QuickTime or Matroska headers when the video is added to
+/// the library. The capture date and dimensions are also available through
+/// Library::get_metadata(), like for any picture.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VideoInfo {
+    pub(crate) duration: Option<f64>,
+    pub(crate) width: Option<u32>,
+    pub(crate) height: Option<u32>,
+    pub(crate) creation_time: Option<NaiveDateTime>,
+    pub(crate) codec: Option<String>,
+}
+
+/// The video part of a Live Photo or motion photo
+///
+/// Apple Live Photos keep their video in a separate file, which is in the
+/// library as well. Android motion photos append it to the JPEG file, where
+/// it can be read as an MP4 file of its own.
+#[derive(Debug, Clone)]
+pub enum LiveVideo {
+    /// The video file paired with the still.
+    Paired(Picture),
+    /// The video embedded in the still, at `offset` bytes into the file.
+    Embedded { offset: u64, length: u64 },
+}
+
+// Instance methods
+impl VideoInfo {
+    /// Get the duration of the video, in seconds.
+    pub fn get_duration(&self) -> Option<f64> {
+        self.duration
+    }
+    /// Get the width of the first video track, in pixels.
+    pub fn get_width(&self) -> Option<u32> {
+        self.width
+    }
+    /// Get the height of the first video track, in pixels.
+    pub fn get_height(&self) -> Option<u32> {
+        self.height
+    }
+    /// Get the time the video was recorded, in UTC.
+    pub fn get_creation_time(&self) -> Option<NaiveDateTime> {
+        self.creation_time
+    }
+    /// Get the codec of the first video track, as named by the container
+    /// (`avc1`, `hvc1`, `V_VP9`, ...).
+    pub fn get_codec(&self) -> Option<String> {
+        self.codec.clone()
+    }
+}
+
+/// A picture or a video of the library
+///
+/// Both are library entries sharing the same ids, tags, albums and filters,
+/// so the entry itself is a Picture either way. Videos come along with their
+/// container metadata.
+#[derive(Debug, Clone)]
+pub enum Media {
+    Picture(Picture),
+    Video(Picture, VideoInfo),
+}
+
+// Instance methods
+impl Media {
+    /// Get the library entry of the picture or video.
+    pub fn get_entry(&self) -> &Picture {
+        match self {
+            Media::Picture(picture) | Media::Video(picture, _) => picture,
+        }
+    }
+    /// Get whether this is a picture or a video.
+    pub fn get_kind(&self) -> MediaKind {
+        match self {
+            Media::Picture(_) => MediaKind::Picture,
+            Media::Video(..) => MediaKind::Video,
+        }
+    }
+    /// Get the container metadata of a video. Returns None for pictures.
+    pub fn get_video_info(&self) -> Option<&VideoInfo> {
+        match self {
+            Media::Picture(_) => None,
+            Media::Video(_, info) => Some(info),
+        }
+    }
+}
+
+impl From<Media> for Picture {
+    fn from(media: Media) -> Self {
+        match media {
+            Media::Picture(picture) | Media::Video(picture, _) => picture,
+        }
+    }
+}
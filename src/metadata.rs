@@ -0,0 +1,244 @@
+use chrono::NaiveDateTime;
+use exif::{In, Tag, Value};
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read, Seek};
+use std::path::Path;
+
+use crate::media;
+use crate::systems::preview;
+
+/// Number of bytes read from Olympus and Panasonic RAW files to find their
+/// EXIF data, which comes before the image data.
+const RAW_EXIF_SIZE: u64 = 1024 * 1024;
+
+/// Capture metadata of a picture
+///
+/// Read from the EXIF data of the file when the picture is added to the
+/// library, so that clients can sort and display pictures without parsing
+/// every file again. Every field is optional, as most formats (and most
+/// editing software) don't keep all of them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    pub(crate) date_taken: Option<NaiveDateTime>,
+    pub(crate) camera_make: Option<String>,
+    pub(crate) camera_model: Option<String>,
+    pub(crate) lens: Option<String>,
+    pub(crate) exposure_time: Option<f64>,
+    pub(crate) f_number: Option<f64>,
+    pub(crate) iso: Option<u32>,
+    pub(crate) focal_length: Option<f64>,
+    pub(crate) orientation: Option<u32>,
+    pub(crate) latitude: Option<f64>,
+    pub(crate) longitude: Option<f64>,
+    pub(crate) width: Option<u32>,
+    pub(crate) height: Option<u32>,
+}
+
+// Instance methods
+impl Metadata {
+    /// Get the date and time the picture was taken, in the camera's local time.
+    pub fn get_date_taken(&self) -> Option<NaiveDateTime> {
+        self.date_taken
+    }
+    /// Get the manufacturer of the camera.
+    pub fn get_camera_make(&self) -> Option<String> {
+        self.camera_make.clone()
+    }
+    /// Get the model of the camera.
+    pub fn get_camera_model(&self) -> Option<String> {
+        self.camera_model.clone()
+    }
+    /// Get the lens model.
+    pub fn get_lens(&self) -> Option<String> {
+        self.lens.clone()
+    }
+    /// Get the exposure time, in seconds.
+    pub fn get_exposure_time(&self) -> Option<f64> {
+        self.exposure_time
+    }
+    /// Get the aperture as an f-number.
+    pub fn get_f_number(&self) -> Option<f64> {
+        self.f_number
+    }
+    /// Get the ISO sensitivity.
+    pub fn get_iso(&self) -> Option<u32> {
+        self.iso
+    }
+    /// Get the focal length, in millimeters.
+    pub fn get_focal_length(&self) -> Option<f64> {
+        self.focal_length
+    }
+    /// Get the EXIF orientation, from 1 (upright) to 8.
+    pub fn get_orientation(&self) -> Option<u32> {
+        self.orientation
+    }
+    /// Get the latitude in decimal degrees, negative south of the equator.
+    pub fn get_latitude(&self) -> Option<f64> {
+        self.latitude
+    }
+    /// Get the longitude in decimal degrees, negative west of Greenwich.
+    pub fn get_longitude(&self) -> Option<f64> {
+        self.longitude
+    }
+    /// Get the width of the picture in pixels, as stored in the file.
+    pub fn get_width(&self) -> Option<u32> {
+        self.width
+    }
+    /// Get the height of the picture in pixels, as stored in the file.
+    pub fn get_height(&self) -> Option<u32> {
+        self.height
+    }
+}
+
+/// Read the metadata of a file. Files without EXIF data (or that can't be
+/// read at all) give empty metadata rather than an error, as this should
+/// never prevent a picture from being added to the library.
+pub(crate) fn read(path: &Path) -> Metadata {
+    let mut metadata = match read_exif(path) {
+        Some(exif) => from_exif(&exif),
+        None => Metadata::default(),
+    };
+
+    // The image header is more reliable than EXIF, which is often left
+    // untouched when pictures are resized. RAW files are the exception, as
+    // the TIFF header they share describes their small preview.
+    if media::is_raw_mime(&media::mime_from_extension(path)) {
+        return metadata;
+    }
+    if let Ok((width, height)) = image::image_dimensions(path) {
+        metadata.width = Some(width);
+        metadata.height = Some(height);
+    }
+    metadata
+}
+
+pub(crate) fn read_exif(path: &Path) -> Option<exif::Exif> {
+    let file = File::open(path).ok()?;
+    let mut reader = BufReader::new(file);
+    match exif::Reader::new().read_from_container(&mut reader) {
+        Ok(exif) => Some(exif),
+        Err(_) => read_raw_exif(path),
+    }
+}
+
+/// Read the EXIF data of the camera RAW formats that kamadak-exif doesn't
+/// recognize as TIFF.
+fn read_raw_exif(path: &Path) -> Option<exif::Exif> {
+    let mut file = File::open(path).ok()?;
+    let mut magic = [0; 4];
+    file.read_exact(&mut magic).ok()?;
+    match &magic {
+        // Fujifilm keeps it in the JPEG preview.
+        b"FUJI" => {
+            let jpeg = preview::read_raf_jpeg(&mut file)?;
+            exif::Reader::new()
+                .read_from_container(&mut Cursor::new(jpeg))
+                .ok()
+        }
+        // Olympus and Panasonic use TIFF with their own magic number.
+        // Values past the part read, like a maker note at the end of the
+        // file, are skipped rather than failing the whole EXIF data.
+        b"IIRO" | b"IIRS" | b"MMOR" | b"IIU\0" => {
+            let mut data = Vec::new();
+            file.rewind().ok()?;
+            file.take(RAW_EXIF_SIZE).read_to_end(&mut data).ok()?;
+            let magic: &[u8] = match data[0] {
+                b'I' => &[42, 0],
+                _ => &[0, 42],
+            };
+            data[2..4].copy_from_slice(magic);
+            exif::Reader::new()
+                .continue_on_error(true)
+                .read_raw(data)
+                .or_else(|err| err.distill_partial_result(|_| {}))
+                .ok()
+        }
+        _ => None,
+    }
+}
+
+fn from_exif(exif: &exif::Exif) -> Metadata {
+    let date_taken = [Tag::DateTimeOriginal, Tag::DateTimeDigitized, Tag::DateTime]
+        .iter()
+        .find_map(|tag| date_field(exif, *tag));
+
+    Metadata {
+        date_taken,
+        camera_make: string_field(exif, Tag::Make),
+        camera_model: string_field(exif, Tag::Model),
+        lens: string_field(exif, Tag::LensModel),
+        exposure_time: float_field(exif, Tag::ExposureTime),
+        f_number: float_field(exif, Tag::FNumber),
+        iso: uint_field(exif, Tag::PhotographicSensitivity),
+        focal_length: float_field(exif, Tag::FocalLength),
+        orientation: uint_field(exif, Tag::Orientation),
+        latitude: coordinate(exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, b'S'),
+        longitude: coordinate(exif, Tag::GPSLongitude, Tag::GPSLongitudeRef, b'W'),
+        width: uint_field(exif, Tag::PixelXDimension),
+        height: uint_field(exif, Tag::PixelYDimension),
+    }
+}
+
+fn string_field(exif: &exif::Exif, tag: Tag) -> Option<String> {
+    let field = exif.get_field(tag, In::PRIMARY)?;
+    match field.value {
+        Value::Ascii(ref values) => {
+            let text = String::from_utf8_lossy(values.first()?);
+            let text = text.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+            if text.is_empty() {
+                None
+            } else {
+                Some(text.to_string())
+            }
+        }
+        _ => None,
+    }
+}
+
+fn uint_field(exif: &exif::Exif, tag: Tag) -> Option<u32> {
+    exif.get_field(tag, In::PRIMARY)?.value.get_uint(0)
+}
+
+fn float_field(exif: &exif::Exif, tag: Tag) -> Option<f64> {
+    match exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Rational(ref values) => values.first().map(|value| value.to_f64()),
+        Value::SRational(ref values) => values.first().map(|value| value.to_f64()),
+        _ => None,
+    }
+    .filter(|value| value.is_finite())
+}
+
+fn date_field(exif: &exif::Exif, tag: Tag) -> Option<NaiveDateTime> {
+    let field = exif.get_field(tag, In::PRIMARY)?;
+    let date = match field.value {
+        Value::Ascii(ref values) => exif::DateTime::from_ascii(values.first()?).ok()?,
+        _ => return None,
+    };
+    chrono::NaiveDate::from_ymd_opt(date.year as i32, date.month as u32, date.day as u32)?
+        .and_hms_opt(date.hour as u32, date.minute as u32, date.second as u32)
+}
+
+/// Convert degrees, minutes and seconds to decimal degrees. `negative` is the
+/// reference (S or W) for which the coordinate is negative.
+fn coordinate(exif: &exif::Exif, tag: Tag, reference: Tag, negative: u8) -> Option<f64> {
+    let parts = match exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Rational(ref values) if values.len() == 3 => values
+            .iter()
+            .map(|value| value.to_f64())
+            .collect::<Vec<f64>>(),
+        _ => return None,
+    };
+    let mut degrees = parts[0] + parts[1] / 60.0 + parts[2] / 3600.0;
+    if !degrees.is_finite() {
+        return None;
+    }
+
+    if let Some(field) = exif.get_field(reference, In::PRIMARY) {
+        if let Value::Ascii(ref values) = field.value {
+            if values.first().and_then(|value| value.first()) == Some(&negative) {
+                degrees = -degrees;
+            }
+        }
+    }
+    Some(degrees)
+}
@@ -1,97 +1,220 @@
-use image::{open, GenericImageView};
 use path_absolutize::Absolutize;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::error::LumenzaError;
 use crate::library;
+use crate::media::{self, MediaKind, VideoInfo};
+use crate::metadata::{self, Metadata};
+use crate::systems::container;
+use crate::systems::hash;
+use crate::systems::live;
+use crate::systems::thumbnails;
+use crate::thumbnail::ThumbnailCrop;
 
 /// Picture management functions
 /// 
 /// Only gives access to select picture attributes that are necessary for 
 /// initial presentation of a photo gallery, etc. The reason for this is 
 /// speed, allowing simpler clients to present data faster. For more complex 
-/// filtering/searching, use the (not yet implemented) filter module.
+/// filtering/searching, use the filter module.
+#[derive(Debug, Clone)]
 pub struct Picture {
-    pub(crate) _id: u32,
+    pub(crate) id: u32,
     pub(crate) filename: PathBuf,
     pub(crate) thumbnail: PathBuf,
+    pub(crate) mime: String,
+    pub(crate) kind: MediaKind,
+    pub(crate) raw: bool,
+    pub(crate) hash: Option<String>,
+    pub(crate) missing: bool,
+    pub(crate) thumbnails: Vec<thumbnails::Thumbnail>,
 }
 
 impl Picture {
     /// Create a new picture entry in the library
-    pub(crate) fn new(library: &library::Library, file: &PathBuf) -> Result<Self, LumenzaError> {
+    pub(crate) fn new(library: &library::Library, file: &Path) -> Result<Self, LumenzaError> {
+        Picture::prepare(library, file)?.insert(library)
+    }
+
+    /// Build the picture for a file that is not in the library yet, without
+    /// inserting it. This is where the content hash is computed.
+    pub(crate) fn prepare(library: &library::Library, file: &Path) -> Result<Self, LumenzaError> {
         let full_path = file.absolutize().unwrap_or_default().into_owned();
 
         // Can't use default error because std::io::error is not implemented in LumenzaError.
-        if !std::fs::metadata(full_path.clone()).is_ok() {
+        if std::fs::metadata(full_path.clone()).is_err() {
             return Err(LumenzaError::FileNotFound());
         }
 
-        let picture = Picture {
-            _id: 0,
+        let mime = media::guess_mime(&full_path);
+        let mut picture = Picture {
+            id: 0,
             filename: full_path,
             thumbnail: PathBuf::new(),
+            kind: MediaKind::from_mime(&mime).unwrap_or(MediaKind::Picture),
+            raw: media::is_raw_mime(&mime),
+            mime,
+            hash: None,
+            missing: false,
+            thumbnails: Vec::new(),
         };
 
         // If picture was already in the database, skip insertion.
         let res = library.database.lookup_picture(&picture)?;
-        if res == true {
+        if res {
             return Err(LumenzaError::PictureAlreadyInLibrary());
         }
 
-        library.database.write_picture(&picture)?;
+        picture.hash = Some(hash::hash_file(&picture.filename)?);
+        Ok(picture)
+    }
+
+    /// Insert a prepared picture, along with its metadata.
+    pub(crate) fn insert(self, library: &library::Library) -> Result<Self, LumenzaError> {
+        let mut picture = self;
+        picture.id = library.database.write_picture(&picture)?;
+        library
+            .database
+            .set_picture_fingerprint(picture.id, hash::fingerprint(&picture.filename))?;
+        picture.write_metadata(library)?;
+
+        // Live Photos are paired once both of their files are in.
+        if let Some(id) = live::content_identifier(&picture.filename, picture.kind) {
+            library.database.write_content_identifier(picture.id, &id)?;
+            library.database.pair_live_photos()?;
+        }
+        if picture.kind == MediaKind::Picture {
+            if let Some((offset, length)) = live::embedded_video(&picture.filename) {
+                library
+                    .database
+                    .write_embedded_video(picture.id, offset, length)?;
+            }
+        }
 
         // Check if the insert was successful.
         let res = library.database.lookup_picture(&picture)?;
-        if res == false {
+        if !res {
             return Err(LumenzaError::DatabaseError(rusqlite::Error::InvalidQuery));
         }
 
         Ok(picture)
     }
+
+    /// Read the metadata of the file, or the container metadata of a video,
+    /// into the database.
+    pub(crate) fn write_metadata(&self, library: &library::Library) -> Result<(), LumenzaError> {
+        match self.kind {
+            MediaKind::Picture => {
+                let metadata = metadata::read(&self.filename);
+                library.database.write_metadata(self.id, &metadata)
+            }
+            MediaKind::Video => {
+                let info = container::read(&self.filename);
+                library.database.write_video_info(self.id, &info)?;
+                library
+                    .database
+                    .write_metadata(self.id, &video_metadata(&info))
+            }
+        }
+    }
 }
 
 // Instance methods
 impl Picture {
+    /// Get the database id of the picture.
+    pub fn get_id(&self) -> u32 {
+        self.id
+    }
+    /// Get the kind of media, picture or video.
+    pub fn get_kind(&self) -> MediaKind {
+        self.kind
+    }
+    /// Whether the picture is a camera RAW file. Lumenza thumbnails them
+    /// from the preview embedded by the camera; use a Thumbnailer to decode
+    /// them fully.
+    pub fn is_raw(&self) -> bool {
+        self.raw
+    }
+    /// Get the mime type of the file, like `image/jpeg` or
+    /// `image/x-nikon-nef`. It is detected from the content of the file, so
+    /// misnamed files get their actual type.
+    pub fn get_mime(&self) -> String {
+        self.mime.clone()
+    }
+    /// Get the hash of the picture's content. Identical files have the same
+    /// hash. Pictures added by older versions of Lumenza may not have one yet.
+    pub fn get_hash(&self) -> Option<String> {
+        self.hash.clone()
+    }
+    /// Whether the file of the picture could not be found the last time the
+    /// library was verified. See Library::verify().
+    pub fn is_missing(&self) -> bool {
+        self.missing
+    }
     /// Get the full filename of the picture. 
     pub fn get_filename(&self) -> PathBuf {
         self.filename.to_path_buf()
     }
     /// Set new filename for picture. Mostly used when pictures are 
-    /// renamed/moved.
-    pub fn set_filename(&mut self, filename: &PathBuf) -> Result<(), LumenzaError> {
-        // TODO: Check if the file exists.
-        self.filename = filename.clone();
+    /// renamed/moved. The file must exist. Use Library::update_picture() to
+    /// save the change.
+    pub fn set_filename(&mut self, filename: &Path) -> Result<(), LumenzaError> {
+        let full_path = filename.absolutize().unwrap_or_default().into_owned();
+        if !full_path.is_file() {
+            return Err(LumenzaError::FileNotFound());
+        }
+        self.filename = full_path;
+        self.missing = false;
         Ok(())
     }
     /// Get full path to the picture's thumbnail.
     pub fn get_thumbnail(&self) -> PathBuf {
         self.thumbnail.to_path_buf()
     }
+    /// Get the thumbnail best suited to display the picture `size` pixels
+    /// wide: the smallest one at least that wide, or the largest one if none
+    /// is. Thumbnails of the whole picture are preferred to cropped ones.
+    /// Pictures without generated thumbnails give get_thumbnail().
+    pub fn thumbnail_for(&self, size: u32) -> PathBuf {
+        match self
+            .thumbnails
+            .iter()
+            .any(|(_, crop, _)| *crop == ThumbnailCrop::Fit)
+        {
+            true => self.cropped_thumbnail_for(size, ThumbnailCrop::Fit),
+            false => self.best_thumbnail(size, |_| true),
+        }
+    }
+    /// Same as thumbnail_for(), among the thumbnails cropped the given way.
+    pub fn cropped_thumbnail_for(&self, size: u32, crop: ThumbnailCrop) -> PathBuf {
+        self.best_thumbnail(size, |other| other == crop)
+    }
+    fn best_thumbnail(&self, size: u32, crop: impl Fn(ThumbnailCrop) -> bool) -> PathBuf {
+        let mut thumbnails = self.thumbnails.iter().filter(|(_, other, _)| crop(*other));
+        let best = thumbnails
+            .clone()
+            .find(|(width, _, _)| *width >= size)
+            .or(thumbnails.next_back());
+        match best {
+            Some((_, _, thumbnail)) => thumbnail.to_path_buf(),
+            None => self.get_thumbnail(),
+        }
+    }
     /// Set a thumbnail (that can be anywhere) for the picture. This enables clients
     /// to use other thumbnailing libraries, supporting custom formats, or simply
-    /// just having faster thumbnail generation.
-    pub fn set_thumbnail(&mut self, thumbnail: &PathBuf) {
-        self.thumbnail = thumbnail.clone();
+    /// just having faster thumbnail generation. Use Library::update_picture()
+    /// to save the change.
+    pub fn set_thumbnail(&mut self, thumbnail: &Path) {
+        self.thumbnail = thumbnail.to_path_buf();
     }
+}
 
-    /// Default thumbnail generation using the image crate.
-    pub(crate) fn generate_thumbnail(&self, thumbnail: &PathBuf) -> Result<(), LumenzaError> {
-        // Open the image
-        let img = open(&self.filename).map_err(|_| LumenzaError::ImageError())?;
-
-        // Calculate the new height while maintaining the aspect ratio
-        let (width, height) = img.dimensions();
-        let new_width = 256;
-        let new_height = (height as f32 * new_width as f32 / width as f32) as u32;
-
-        // Resize the image
-        let resized_img =
-            img.resize_exact(new_width, new_height, image::imageops::FilterType::Lanczos3);
-
-        // Save the resized image to a new file
-        resized_img.save(thumbnail).map_err(|_| LumenzaError::ImageError())?;
-
-        Ok(())
+/// Capture metadata of a video, taken from its container.
+fn video_metadata(info: &VideoInfo) -> Metadata {
+    Metadata {
+        date_taken: info.creation_time,
+        width: info.width,
+        height: info.height,
+        ..Metadata::default()
     }
 }
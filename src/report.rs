@@ -0,0 +1,128 @@
+use std::path::PathBuf;
+
+use crate::error::LumenzaError;
+use crate::picture::Picture;
+
+/// Outcome of Library::process_folder()
+///
+/// Every file found in the folder is in one of the lists, except hidden
+/// ones. Files that can't be read don't stop the scan; they are listed along
+/// with the error instead.
+#[derive(Debug, Default)]
+pub struct ScanReport {
+    pub(crate) added: Vec<Picture>,
+    pub(crate) updated: Vec<Picture>,
+    pub(crate) skipped: Vec<PathBuf>,
+    pub(crate) unreadable: Vec<(PathBuf, LumenzaError)>,
+    pub(crate) unsupported: Vec<PathBuf>,
+}
+
+// Instance methods
+impl ScanReport {
+    /// Get the pictures added to the library.
+    pub fn get_added(&self) -> &[Picture] {
+        &self.added
+    }
+    /// Get the pictures that were already in the library, but were moved,
+    /// modified or back after going missing.
+    pub fn get_updated(&self) -> &[Picture] {
+        &self.updated
+    }
+    /// Get the files that were already in the library, unchanged.
+    pub fn get_skipped(&self) -> &[PathBuf] {
+        &self.skipped
+    }
+    /// Get the files that could not be read, and why.
+    pub fn get_unreadable(&self) -> &[(PathBuf, LumenzaError)] {
+        &self.unreadable
+    }
+    /// Get the files that are neither pictures nor videos.
+    pub fn get_unsupported(&self) -> &[PathBuf] {
+        &self.unsupported
+    }
+}
+
+/// Outcome of Library::verify()
+///
+/// Lists the pictures whose file could not be found, and the ones that were
+/// missing but are back, for instance after an external drive was mounted
+/// again.
+#[derive(Debug, Clone, Default)]
+pub struct VerifyReport {
+    pub(crate) missing: Vec<Picture>,
+    pub(crate) reattached: Vec<Picture>,
+}
+
+// Instance methods
+impl VerifyReport {
+    /// Get every picture whose file is missing, including the ones that were
+    /// already missing before.
+    pub fn get_missing(&self) -> Vec<Picture> {
+        self.missing.clone()
+    }
+    /// Get the pictures that were missing, and whose file is back.
+    pub fn get_reattached(&self) -> Vec<Picture> {
+        self.reattached.clone()
+    }
+}
+
+/// Outcome of Library::generate_thumbnails()
+///
+/// A picture that can't be thumbnailed, like a corrupted file, does not stop
+/// the others; it is listed along with the error instead.
+#[derive(Debug, Default)]
+pub struct ThumbnailReport {
+    pub(crate) generated: usize,
+    pub(crate) skipped: usize,
+    pub(crate) failed: Vec<(Picture, LumenzaError)>,
+    pub(crate) cancelled: bool,
+}
+
+// Instance methods
+impl ThumbnailReport {
+    /// Get the number of thumbnails generated.
+    pub fn get_generated(&self) -> usize {
+        self.generated
+    }
+    /// Get the number of pictures whose thumbnail was already up to date.
+    pub fn get_skipped(&self) -> usize {
+        self.skipped
+    }
+    /// Get the pictures that could not be thumbnailed, and why.
+    pub fn get_failed(&self) -> &[(Picture, LumenzaError)] {
+        &self.failed
+    }
+    /// Whether the generation was cancelled before every picture was done.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled
+    }
+}
+
+/// Outcome of Library::remove_folder()
+///
+/// Pictures of the folder are either deleted from the library, or detached:
+/// hidden like missing pictures, but left alone by Library::verify() and
+/// Library::purge_missing(), keeping their metadata, tags and albums until
+/// the folder is added again.
+#[derive(Debug, Clone, Default)]
+pub struct RemovalReport {
+    pub(crate) deleted: Vec<Picture>,
+    pub(crate) detached: Vec<Picture>,
+    pub(crate) thumbnails: usize,
+}
+
+// Instance methods
+impl RemovalReport {
+    /// Get the pictures deleted from the library.
+    pub fn get_deleted(&self) -> &[Picture] {
+        &self.deleted
+    }
+    /// Get the pictures kept in the library, detached from their folder.
+    pub fn get_detached(&self) -> &[Picture] {
+        &self.detached
+    }
+    /// Get the number of thumbnail files deleted.
+    pub fn get_removed_thumbnails(&self) -> usize {
+        self.thumbnails
+    }
+}
@@ -0,0 +1,218 @@
+use chrono::NaiveDateTime;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::media::MediaKind;
+use crate::picture::Picture;
+
+/// Stack management
+///
+/// A stack groups files that show the same shot, like the RAW and JPEG files
+/// written together by a camera, the pictures of a burst or the edited
+/// copies of a picture. One of them is the top of the stack, and stands for
+/// the others in a gallery (see Filter::StackTops), while the rest stay
+/// reachable with Library::list_stack_pictures(). A picture is in at most
+/// one stack.
+#[derive(Debug, Clone)]
+pub struct Stack {
+    pub(crate) id: u32,
+    pub(crate) kind: StackKind,
+    pub(crate) top: u32,
+    pub(crate) picture_count: u32,
+}
+
+/// Why the pictures of a stack were grouped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackKind {
+    /// Created by the client with Library::create_stack().
+    Manual,
+    /// The same picture in several formats, like `DSC001.NEF` and
+    /// `DSC001.JPG`.
+    SameName,
+    /// A picture and its edited copies, like `IMG_0001 (1).jpg` or
+    /// `IMG_0001-edited.jpg`.
+    Edited,
+    /// Pictures taken in quick succession.
+    Burst,
+}
+
+/// Pictures of a folder taken at most this many seconds apart are a burst.
+const BURST_INTERVAL: i64 = 1;
+
+/// Suffixes added to the name of a picture by editors and file managers.
+const EDIT_SUFFIXES: &[&str] = &[
+    "-edited", "_edited", " edited", "-edit", "_edit", "-copy", "_copy", " copy",
+];
+
+// Static methods
+impl StackKind {
+    pub(crate) fn from_i64(kind: i64) -> Self {
+        match kind {
+            1 => StackKind::SameName,
+            2 => StackKind::Edited,
+            3 => StackKind::Burst,
+            _ => StackKind::Manual,
+        }
+    }
+}
+
+// Instance methods
+impl StackKind {
+    pub(crate) fn to_i64(self) -> i64 {
+        match self {
+            StackKind::Manual => 0,
+            StackKind::SameName => 1,
+            StackKind::Edited => 2,
+            StackKind::Burst => 3,
+        }
+    }
+}
+
+// Instance methods
+impl Stack {
+    /// Get the database id of the stack.
+    pub fn get_id(&self) -> u32 {
+        self.id
+    }
+    /// Get the reason the pictures were stacked.
+    pub fn get_kind(&self) -> StackKind {
+        self.kind
+    }
+    /// Get the id of the picture at the top of the stack.
+    pub fn get_top(&self) -> u32 {
+        self.top
+    }
+    /// Get the number of pictures in the stack, including the top.
+    pub fn get_picture_count(&self) -> u32 {
+        self.picture_count
+    }
+}
+
+/// A picture that is not stacked yet, and its capture date.
+pub(crate) type Candidate = (Picture, Option<NaiveDateTime>);
+
+/// Group related pictures. Each group has at least two pictures, its top
+/// first. Only pictures are stacked, videos are left alone.
+pub(crate) fn group_related(candidates: Vec<Candidate>) -> Vec<(StackKind, Vec<Picture>)> {
+    let mut groups = Vec::new();
+    let candidates: Vec<_> = candidates
+        .into_iter()
+        .filter(|(picture, _)| picture.kind == MediaKind::Picture)
+        .collect();
+
+    // Same folder and name, different extensions.
+    let mut names: HashMap<(PathBuf, String), Vec<Candidate>> = HashMap::new();
+    for candidate in candidates {
+        names
+            .entry(name_key(&candidate.0))
+            .or_default()
+            .push(candidate);
+    }
+    let mut rest = Vec::new();
+    for (_, mut group) in names {
+        if group.len() < 2 {
+            rest.append(&mut group);
+            continue;
+        }
+        // The top is a file every client can display, JPEG if possible.
+        group.sort_by_key(|(picture, _)| (picture.raw, picture.mime != "image/jpeg", picture.id));
+        groups.push((
+            StackKind::SameName,
+            group.into_iter().map(|(picture, _)| picture).collect(),
+        ));
+    }
+
+    // Edited copies, next to their original. Copies of copies are left out.
+    let originals: HashMap<(PathBuf, String), usize> = rest
+        .iter()
+        .enumerate()
+        .map(|(index, (picture, _))| (name_key(picture), index))
+        .filter(|((_, name), _)| strip_edit_suffix(name).is_none())
+        .collect();
+    let mut edits: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (index, (picture, _)) in rest.iter().enumerate() {
+        let (folder, name) = name_key(picture);
+        if let Some(base) = strip_edit_suffix(&name) {
+            if let Some(&original) = originals.get(&(folder, base.to_string())) {
+                edits.entry(original).or_default().push(index);
+            }
+        }
+    }
+    let mut stacked = vec![false; rest.len()];
+    for (original, mut copies) in edits {
+        // The latest edit is the top.
+        copies.sort_by_key(|&index| std::cmp::Reverse(rest[index].0.id));
+        copies.push(original);
+        for &index in &copies {
+            stacked[index] = true;
+        }
+        groups.push((
+            StackKind::Edited,
+            copies
+                .into_iter()
+                .map(|index| rest[index].0.clone())
+                .collect(),
+        ));
+    }
+
+    // Bursts, in the order they were taken.
+    let mut dated: Vec<(PathBuf, NaiveDateTime, Picture)> = rest
+        .into_iter()
+        .zip(stacked)
+        .filter(|(_, stacked)| !stacked)
+        .filter_map(|((picture, date), _)| Some((name_key(&picture).0, date?, picture)))
+        .collect();
+    dated.sort_by(|a, b| (&a.0, a.1, a.2.id).cmp(&(&b.0, b.1, b.2.id)));
+    let mut bursts: Vec<Vec<(PathBuf, NaiveDateTime, Picture)>> = Vec::new();
+    for item in dated {
+        match bursts.last_mut() {
+            Some(burst) if is_burst(&burst[burst.len() - 1], &item) => burst.push(item),
+            _ => bursts.push(vec![item]),
+        }
+    }
+    for burst in bursts.into_iter().filter(|burst| burst.len() >= 2) {
+        groups.push((
+            StackKind::Burst,
+            burst.into_iter().map(|(_, _, picture)| picture).collect(),
+        ));
+    }
+
+    groups
+}
+
+/// Whether the second picture follows the first in a burst.
+fn is_burst(
+    (folder, date, _): &(PathBuf, NaiveDateTime, Picture),
+    next: &(PathBuf, NaiveDateTime, Picture),
+) -> bool {
+    *folder == next.0 && (next.1 - *date).num_seconds() <= BURST_INTERVAL
+}
+
+/// Folder of the picture, and its file name without extension, ignoring
+/// case.
+fn name_key(picture: &Picture) -> (PathBuf, String) {
+    let folder = picture.filename.parent().unwrap_or(&picture.filename);
+    let stem = picture
+        .filename
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    (folder.to_path_buf(), stem)
+}
+
+/// Name of the original of an edited copy, like `img_0001` for
+/// `img_0001-edited` or `img_0001 (2)`.
+fn strip_edit_suffix(name: &str) -> Option<&str> {
+    if let Some(base) = EDIT_SUFFIXES
+        .iter()
+        .find_map(|suffix| name.strip_suffix(suffix))
+    {
+        return Some(base);
+    }
+    // Copies numbered by file managers.
+    let (base, number) = name.strip_suffix(')')?.rsplit_once(" (")?;
+    match !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()) {
+        true => Some(base),
+        false => None,
+    }
+}
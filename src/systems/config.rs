@@ -1,7 +1,8 @@
 use crate::error::LumenzaError;
+use crate::thumbnail::ThumbnailProfile;
 
 use serde::{Deserialize, Serialize};
-use std::{fs, path::PathBuf};
+use std::{fs, path::Path, path::PathBuf};
 
 #[derive(Serialize, Deserialize)]
 pub struct Config {
@@ -10,39 +11,50 @@ pub struct Config {
     folders_paths: Vec<String>,
     thumbnails_path: String,
     database_path: String,
+    // Tables go last in TOML. The `quality` of a profile only applies to
+    // JPEG, PNG and WebP thumbnails are lossless.
+    #[serde(default = "default_thumbnail_profiles")]
+    thumbnail_profiles: Vec<ThumbnailProfile>,
+}
+
+fn default_thumbnail_profiles() -> Vec<ThumbnailProfile> {
+    vec![ThumbnailProfile::default()]
 }
 
 // Static methods
 impl Config {
     pub fn new(
-        config_path: &PathBuf,
-        folders_paths: &Vec<PathBuf>,
-        thumbnails_path: &PathBuf,
-        database_path: &PathBuf,
+        config_path: &Path,
+        folders_paths: &[PathBuf],
+        thumbnails_path: &Path,
+        database_path: &Path,
     ) -> Result<Self, LumenzaError> {
         // Convert all PathBufs to Strings.
         let mut folders_strings = Vec::new();
         for path in folders_paths {
-            folders_strings.push(path.as_path().to_str().unwrap().to_string());
+            folders_strings.push(path.to_str().unwrap().to_string());
         }
-        let config_string = String::from(config_path.as_path().to_str().unwrap());
-        let thumbnails_string = String::from(thumbnails_path.as_path().to_str().unwrap());
-        let database_string = String::from(database_path.as_path().to_str().unwrap());
+        let config_string = String::from(config_path.to_str().unwrap());
+        let thumbnails_string = String::from(thumbnails_path.to_str().unwrap());
+        let database_string = String::from(database_path.to_str().unwrap());
 
         let config = Config {
             config_path: config_string,
             folders_paths: folders_strings,
             thumbnails_path: thumbnails_string,
             database_path: database_string,
+            thumbnail_profiles: default_thumbnail_profiles(),
         };
         config.write_config()?;
 
         Ok(config)
     }
-    pub fn open(config_path: &PathBuf) -> Result<Self, LumenzaError> {
+    pub fn open(config_path: &Path) -> Result<Self, LumenzaError> {
         let data = fs::read(config_path).map_err(|_| LumenzaError::IoError())?;
         let text = String::from_utf8(data)?;
-        let config: Config = toml::from_str(&text)?;
+        let mut config: Config = toml::from_str(&text)?;
+        // Not stored in the file itself.
+        config.config_path = config_path.to_str().unwrap().to_string();
         Ok(config)
     }
 }
@@ -63,12 +75,22 @@ impl Config {
         Ok(())
     }
 
-    pub fn add_folder(&mut self, folder: &PathBuf) -> Result<(), LumenzaError> {
-        let folder_string = folder.as_path().to_str().unwrap().to_string();
+    pub fn add_folder(&mut self, folder: &Path) -> Result<(), LumenzaError> {
+        let folder_string = folder.to_str().unwrap().to_string();
         self.folders_paths.push(folder_string);
         self.write_config()?;
         Ok(())
     }
+    /// Returns false if the folder was not in the config.
+    pub fn remove_folder(&mut self, folder: &Path) -> Result<bool, LumenzaError> {
+        let count = self.folders_paths.len();
+        self.folders_paths.retain(|path| Path::new(path) != folder);
+        if self.folders_paths.len() == count {
+            return Ok(false);
+        }
+        self.write_config()?;
+        Ok(true)
+    }
 
     pub fn get_folders_paths(&self) -> Vec<PathBuf> {
         // Convert strings to owned pathbufs.
@@ -84,4 +106,14 @@ impl Config {
     pub fn get_database_path(&self) -> PathBuf {
         PathBuf::from(&self.database_path)
     }
+    pub fn get_thumbnail_profiles(&self) -> Vec<ThumbnailProfile> {
+        self.thumbnail_profiles.clone()
+    }
+    pub fn set_thumbnail_profiles(
+        &mut self,
+        profiles: &[ThumbnailProfile],
+    ) -> Result<(), LumenzaError> {
+        self.thumbnail_profiles = profiles.to_vec();
+        self.write_config()
+    }
 }
@@ -0,0 +1,379 @@
+use chrono::{DateTime, NaiveDateTime};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::media::VideoInfo;
+
+/// Largest `moov` box that will be read in memory. Real files are well
+/// below this, even for hours of footage.
+const MAX_MOOV_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Seconds between 1904-01-01 (ISO base media epoch) and the Unix epoch.
+const MP4_EPOCH_OFFSET: i64 = 2_082_844_800;
+
+/// Seconds between 2001-01-01 (Matroska epoch) and the Unix epoch.
+const MATROSKA_EPOCH_OFFSET: i64 = 978_307_200;
+
+/// Read the container metadata of a video file. MP4 and QuickTime (ISO base
+/// media) files and Matroska/WebM files are supported. Anything that can't
+/// be read gives empty info, the same way pictures without EXIF do.
+pub fn read(path: &Path) -> VideoInfo {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(_) => return VideoInfo::default(),
+    };
+    let mut reader = BufReader::new(file);
+
+    let mut magic = [0u8; 12];
+    if reader.read_exact(&mut magic).is_err() || reader.rewind().is_err() {
+        return VideoInfo::default();
+    }
+
+    if magic[0..4] == [0x1A, 0x45, 0xDF, 0xA3] {
+        read_matroska(&mut reader).unwrap_or_default()
+    } else {
+        read_isobmff(&mut reader).unwrap_or_default()
+    }
+}
+
+// ISO base media file format (MP4, MOV, 3GP, HEIF).
+
+/// A box header: its type, and the size of its payload.
+struct BoxHeader {
+    kind: [u8; 4],
+    size: u64,
+}
+
+/// Read a box header from a stream. `remaining` is the space left in the
+/// parent, used for boxes that extend to the end of it.
+fn read_box_header<R: Read>(reader: &mut R, remaining: u64) -> Option<BoxHeader> {
+    let mut header = [0u8; 8];
+    reader.read_exact(&mut header).ok()?;
+    let size = u32::from_be_bytes(header[0..4].try_into().ok()?) as u64;
+    let kind = header[4..8].try_into().ok()?;
+
+    let size = match size {
+        0 => remaining.checked_sub(8)?,
+        1 => {
+            let mut large = [0u8; 8];
+            reader.read_exact(&mut large).ok()?;
+            u64::from_be_bytes(large).checked_sub(16)?
+        }
+        size => size.checked_sub(8)?,
+    };
+    Some(BoxHeader { kind, size })
+}
+
+/// Find the `moov` box at the top level of the file, and read it in memory.
+pub(crate) fn read_moov<R: Read + Seek>(reader: &mut R) -> Option<Vec<u8>> {
+    let length = reader.seek(SeekFrom::End(0)).ok()?;
+    reader.rewind().ok()?;
+
+    let mut position = 0;
+    while position < length {
+        let header = read_box_header(reader, length - position)?;
+        let start = reader.stream_position().ok()?;
+        if &header.kind == b"moov" {
+            if header.size > MAX_MOOV_SIZE {
+                return None;
+            }
+            let mut moov = vec![0u8; header.size as usize];
+            reader.read_exact(&mut moov).ok()?;
+            return Some(moov);
+        }
+        position = start.checked_add(header.size)?;
+        reader.seek(SeekFrom::Start(position)).ok()?;
+    }
+    None
+}
+
+/// Iterate over the boxes contained in a buffer, giving their type and
+/// payload.
+pub(crate) fn boxes(mut data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut boxes = Vec::new();
+    while data.len() >= 8 {
+        let mut cursor = data;
+        let header = match read_box_header(&mut cursor, data.len() as u64) {
+            Some(header) => header,
+            None => break,
+        };
+        let header_size = data.len() - cursor.len();
+        if header.size > cursor.len() as u64 {
+            break;
+        }
+        let end = header_size + header.size as usize;
+        boxes.push((header.kind, &data[header_size..end]));
+        data = &data[end..];
+    }
+    boxes
+}
+
+/// Find the first child box of the given type.
+pub(crate) fn find_box<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    boxes(data)
+        .into_iter()
+        .find(|(child, _)| child == kind)
+        .map(|(_, payload)| payload)
+}
+
+fn read_isobmff<R: Read + Seek>(reader: &mut R) -> Option<VideoInfo> {
+    let moov = read_moov(reader)?;
+    let mut info = VideoInfo::default();
+
+    if let Some(mvhd) = find_box(&moov, b"mvhd") {
+        let (creation, timescale, duration) = match mvhd.first()? {
+            1 => (
+                be_u64(mvhd, 4)?,
+                be_u32(mvhd, 20)? as u64,
+                be_u64(mvhd, 24)?,
+            ),
+            _ => (
+                be_u32(mvhd, 4)? as u64,
+                be_u32(mvhd, 12)? as u64,
+                be_u32(mvhd, 16)? as u64,
+            ),
+        };
+        if timescale > 0 {
+            info.duration = Some(duration as f64 / timescale as f64);
+        }
+        // Zero means unknown, which many encoders write. Garbage 64-bit
+        // values that don't fit a timestamp are ignored the same way.
+        if creation > 0 {
+            info.creation_time = i64::try_from(creation)
+                .ok()
+                .and_then(|creation| creation.checked_sub(MP4_EPOCH_OFFSET))
+                .and_then(unix_time);
+        }
+    }
+
+    // Use the first video track for dimensions and codec.
+    for (kind, trak) in boxes(&moov) {
+        if &kind != b"trak" {
+            continue;
+        }
+        let mdia = match find_box(trak, b"mdia") {
+            Some(mdia) => mdia,
+            None => continue,
+        };
+        let handler = find_box(mdia, b"hdlr").and_then(|hdlr| hdlr.get(8..12));
+        if handler != Some(b"vide") {
+            continue;
+        }
+
+        if let Some(tkhd) = find_box(trak, b"tkhd") {
+            // Width and height are 16.16 fixed point, at the end of the box.
+            let offset = if tkhd.first() == Some(&1) { 88 } else { 76 };
+            info.width = be_u32(tkhd, offset).map(|width| width >> 16);
+            info.height = be_u32(tkhd, offset + 4).map(|height| height >> 16);
+        }
+        let stsd = find_box(mdia, b"minf")
+            .and_then(|minf| find_box(minf, b"stbl"))
+            .and_then(|stbl| find_box(stbl, b"stsd"));
+        if let Some(entry) = stsd.and_then(|stsd| stsd.get(12..16)) {
+            info.codec = Some(String::from_utf8_lossy(entry).trim().to_string());
+        }
+        break;
+    }
+    Some(info)
+}
+
+// Matroska and WebM (EBML).
+
+const EBML_SEGMENT: u64 = 0x18538067;
+const EBML_INFO: u64 = 0x1549A966;
+const EBML_TIMECODE_SCALE: u64 = 0x2AD7B1;
+const EBML_DURATION: u64 = 0x4489;
+const EBML_DATE_UTC: u64 = 0x4461;
+const EBML_TRACKS: u64 = 0x1654AE6B;
+const EBML_TRACK_ENTRY: u64 = 0xAE;
+const EBML_TRACK_TYPE: u64 = 0x83;
+const EBML_CODEC_ID: u64 = 0x86;
+const EBML_VIDEO: u64 = 0xE0;
+const EBML_PIXEL_WIDTH: u64 = 0xB0;
+const EBML_PIXEL_HEIGHT: u64 = 0xBA;
+const EBML_CLUSTER: u64 = 0x1F43B675;
+
+/// Read a variable length integer. Element ids keep their length marker,
+/// while sizes drop it. Sizes with all bits set are unknown, given as None.
+fn read_vint<R: Read>(reader: &mut R, keep_marker: bool) -> Option<Option<u64>> {
+    let mut first = [0u8; 1];
+    reader.read_exact(&mut first).ok()?;
+    let length = first[0].leading_zeros() as usize + 1;
+    if length > 8 {
+        return None;
+    }
+
+    let mut value = if keep_marker {
+        first[0] as u64
+    } else {
+        (first[0] as u64) & (0xFF >> length)
+    };
+    let mut all_ones = value == (0xFF >> length);
+    for _ in 1..length {
+        let mut byte = [0u8; 1];
+        reader.read_exact(&mut byte).ok()?;
+        all_ones &= byte[0] == 0xFF;
+        value = (value << 8) | byte[0] as u64;
+    }
+    if !keep_marker && all_ones {
+        return Some(None);
+    }
+    Some(Some(value))
+}
+
+/// Read an element header, giving its id and size (None if unknown).
+fn read_element<R: Read>(reader: &mut R) -> Option<(u64, Option<u64>)> {
+    let id = read_vint(reader, true)??;
+    let size = read_vint(reader, false)?;
+    Some((id, size))
+}
+
+/// Elements are read in memory only when small, to avoid reading clusters.
+fn read_payload<R: Read>(reader: &mut R, size: u64) -> Option<Vec<u8>> {
+    if size > MAX_MOOV_SIZE {
+        return None;
+    }
+    let mut payload = vec![0u8; size as usize];
+    reader.read_exact(&mut payload).ok()?;
+    Some(payload)
+}
+
+/// Iterate over the children of an element read in memory.
+fn elements(mut data: &[u8]) -> Vec<(u64, &[u8])> {
+    let mut elements = Vec::new();
+    while !data.is_empty() {
+        let mut cursor = data;
+        let (id, size) = match read_element(&mut cursor) {
+            Some((id, Some(size))) => (id, size as usize),
+            _ => break,
+        };
+        let header_size = data.len() - cursor.len();
+        if size > cursor.len() {
+            break;
+        }
+        elements.push((id, &data[header_size..header_size + size]));
+        data = &data[header_size + size..];
+    }
+    elements
+}
+
+fn ebml_uint(data: &[u8]) -> Option<u64> {
+    if data.is_empty() || data.len() > 8 {
+        return None;
+    }
+    Some(
+        data.iter()
+            .fold(0, |value, byte| (value << 8) | *byte as u64),
+    )
+}
+
+fn ebml_float(data: &[u8]) -> Option<f64> {
+    match data.len() {
+        4 => Some(f32::from_be_bytes(data.try_into().ok()?) as f64),
+        8 => Some(f64::from_be_bytes(data.try_into().ok()?)),
+        _ => None,
+    }
+}
+
+fn read_matroska<R: Read + Seek>(reader: &mut R) -> Option<VideoInfo> {
+    // Skip the EBML header.
+    let (_, size) = read_element(reader)?;
+    reader.seek(SeekFrom::Current(size? as i64)).ok()?;
+
+    let (id, _) = read_element(reader)?;
+    if id != EBML_SEGMENT {
+        return None;
+    }
+
+    let mut info = VideoInfo::default();
+    let mut timecode_scale = 1_000_000;
+    let mut duration = None;
+    // Walk the top level of the segment until the first cluster, as Info
+    // and Tracks come before the actual frames.
+    while let Some((id, size)) = read_element(reader) {
+        // Clusters are often written with an unknown size while recording.
+        if id == EBML_CLUSTER {
+            break;
+        }
+        let size = size?;
+        match id {
+            EBML_INFO => {
+                for (id, data) in elements(&read_payload(reader, size)?) {
+                    match id {
+                        EBML_TIMECODE_SCALE => timecode_scale = ebml_uint(data)?,
+                        EBML_DURATION => duration = ebml_float(data),
+                        EBML_DATE_UTC => {
+                            let nanoseconds = ebml_uint(data)? as i64;
+                            info.creation_time = unix_time(
+                                nanoseconds.div_euclid(1_000_000_000) + MATROSKA_EPOCH_OFFSET,
+                            );
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            EBML_TRACKS => {
+                for (id, entry) in elements(&read_payload(reader, size)?) {
+                    if id != EBML_TRACK_ENTRY {
+                        continue;
+                    }
+                    let fields = elements(entry);
+                    let video = fields
+                        .iter()
+                        .any(|(id, data)| *id == EBML_TRACK_TYPE && ebml_uint(data) == Some(1));
+                    if !video || info.codec.is_some() {
+                        continue;
+                    }
+                    for (id, data) in fields {
+                        match id {
+                            EBML_CODEC_ID => {
+                                let codec = String::from_utf8_lossy(data);
+                                info.codec = Some(codec.trim_end_matches('\0').to_string());
+                            }
+                            EBML_VIDEO => {
+                                for (id, data) in elements(data) {
+                                    match id {
+                                        EBML_PIXEL_WIDTH => {
+                                            info.width = ebml_uint(data).map(|w| w as u32)
+                                        }
+                                        EBML_PIXEL_HEIGHT => {
+                                            info.height = ebml_uint(data).map(|h| h as u32)
+                                        }
+                                        _ => (),
+                                    }
+                                }
+                            }
+                            _ => (),
+                        }
+                    }
+                }
+            }
+            _ => {
+                reader.seek(SeekFrom::Current(size as i64)).ok()?;
+            }
+        }
+    }
+
+    // The duration is a number of ticks, each timecode scale nanoseconds long.
+    info.duration = duration.map(|ticks| ticks * timecode_scale as f64 / 1_000_000_000.0);
+    Some(info)
+}
+
+// Helpers
+
+fn be_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn be_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(
+        data.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+fn unix_time(seconds: i64) -> Option<NaiveDateTime> {
+    DateTime::from_timestamp(seconds, 0).map(|date| date.naive_utc())
+}
@@ -1,12 +1,56 @@
+use crate::album;
 use crate::error::LumenzaError;
+use crate::filter;
+use crate::media;
+use crate::metadata;
 use crate::picture;
+use crate::stack;
+use crate::systems::hash;
+use crate::systems::thumbnails;
+use crate::tag;
+use crate::thumbnail;
 
-use rusqlite::Connection;
-use std::path::PathBuf;
+use chrono::NaiveDateTime;
+use rusqlite::{Connection, OptionalExtension, Row};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
 
 #[path = "sql.rs"]
 mod sql_schema;
 
+/// Columns selected whenever a picture is read, in the order expected by
+/// picture_from_row().
+const PICTURE_COLUMNS: &str = "pictures.id, pictures.filename, pictures.thumbnail, pictures.mime,
+    pictures.kind, pictures.hash, pictures.missing, pictures.raw";
+
+/// Common table expression resolving the full path of every tag.
+const TAG_PATHS: &str = "WITH RECURSIVE tag_paths(id, name, parent, path) AS (
+        SELECT id, name, parent, name FROM tags WHERE parent IS NULL
+        UNION ALL
+        SELECT tags.id, tags.name, tags.parent, tag_paths.path || '/' || tags.name
+        FROM tags JOIN tag_paths ON tags.parent = tag_paths.id
+    )";
+
+/// Stacks left with a single picture are not stacks anymore.
+const DELETE_SMALL_STACKS: &str = "DELETE FROM stacks
+    WHERE (SELECT COUNT(*) FROM stack_pictures WHERE stack_pictures.stack = stacks.id) < 2";
+
+/// Id, fingerprint and missing mark of a picture, to tell whether its file
+/// changed.
+pub type KnownFile = (u32, Option<hash::Fingerprint>, bool);
+
+/// What deleting pictures changed, besides the pictures themselves.
+#[derive(Debug, Default)]
+pub struct Deletion {
+    /// Number of pictures deleted.
+    pub count: usize,
+    /// Albums the pictures were in, which were renumbered.
+    pub albums: Vec<u32>,
+    /// Stacks the pictures were in, which may be deleted if a single
+    /// picture is left.
+    pub stacks: Vec<u32>,
+}
+
 /// The database structure manages the connection to the db and every db entry.
 pub struct Database {
     pub connection: Connection,
@@ -15,43 +59,197 @@ pub struct Database {
 // Static Methods
 impl Database {
     /// Create the database object and file, and inserts the main structure
-    pub fn new(path: &PathBuf) -> Result<Self, LumenzaError> {
+    pub fn new(path: &Path) -> Result<Self, LumenzaError> {
         let db = Database {
             connection: Connection::open(path)?,
         };
-
-        let sql = sql_schema::sql_schema();
-        let sql = sql.as_str();
-
-        db.connection.execute_batch(sql)?;
+        db.initialize()?;
         Ok(db)
     }
-    pub fn open(path: &PathBuf) -> Result<Self, LumenzaError> {
+    /// Open an existing database, upgrading it to the latest schema. Fails if
+    /// the database was created by a newer version of Lumenza.
+    pub fn open(path: &Path) -> Result<Self, LumenzaError> {
         let db = Database {
             connection: Connection::open(path)?,
         };
+        db.initialize()?;
         Ok(db)
     }
 }
 
 // Instance Methods
 impl Database {
-    pub fn write_picture(&self, picture: &picture::Picture) -> Result<bool, LumenzaError> {
-        self.connection
-            .execute(
-                "INSERT INTO pictures (filename) VALUES (?1)",
-                (&picture.filename.to_str(),),
-            )
-            ?;
-        Ok(true)
+    /// Enable foreign keys (they are off by default in SQLite, and only apply
+    /// to the current connection) and bring the schema up to date.
+    fn initialize(&self) -> Result<(), LumenzaError> {
+        self.connection.execute_batch("PRAGMA foreign_keys = ON;")?;
+        self.migrate()
+    }
+
+    /// Get the schema version of the database, which is the number of
+    /// migrations applied to it.
+    pub fn version(&self) -> Result<u32, LumenzaError> {
+        let version = self
+            .connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))?;
+        Ok(version)
+    }
+
+    /// Start a transaction grouping many writes, which is much faster than
+    /// committing each of them on its own.
+    pub fn transaction(&self) -> Result<rusqlite::Transaction<'_>, LumenzaError> {
+        Ok(self.connection.unchecked_transaction()?)
+    }
+
+    /// Apply every migration the database is missing, each in its own
+    /// transaction so that a failure leaves the database at a known version.
+    fn migrate(&self) -> Result<(), LumenzaError> {
+        let version = self.version()?;
+        let latest = sql_schema::MIGRATIONS.len() as u32;
+        if version > latest {
+            return Err(LumenzaError::DatabaseTooNew(version, latest));
+        }
+
+        for (index, sql) in sql_schema::MIGRATIONS
+            .iter()
+            .enumerate()
+            .skip(version as usize)
+        {
+            let tx = self.connection.unchecked_transaction()?;
+            tx.execute_batch(sql)?;
+            tx.pragma_update(None, "user_version", index as u32 + 1)?;
+            tx.commit()?;
+        }
+        // Pictures added before migration 2 have no mime type.
+        if version < 2 {
+            self.backfill_mime()?;
+        }
+        Ok(())
+    }
+
+    /// Fill in the mime type, kind and RAW flag of pictures added before they
+    /// were stored, guessing from their extension since their files may be
+    /// unreachable.
+    fn backfill_mime(&self) -> Result<(), LumenzaError> {
+        let tx = self.connection.unchecked_transaction()?;
+        let pictures = {
+            let mut stmt = tx.prepare("SELECT id, filename FROM pictures WHERE mime IS NULL")?;
+            let rows = stmt.query_map([], |row| {
+                Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?))
+            })?;
+            rows.collect::<Result<Vec<_>, _>>()?
+        };
+        for (id, filename) in pictures {
+            let mime = media::mime_from_extension(Path::new(&filename));
+            let kind = media::MediaKind::from_mime(&mime).unwrap_or(media::MediaKind::Picture);
+            tx.execute(
+                "UPDATE pictures SET mime = ?1, kind = ?2, raw = ?3 WHERE id = ?4",
+                (&mime, kind.to_i64(), media::is_raw_mime(&mime), id),
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Insert the picture, returning the id it was given.
+    pub fn write_picture(&self, picture: &picture::Picture) -> Result<u32, LumenzaError> {
+        self.connection.execute(
+            "INSERT INTO pictures (filename, mime, kind, hash, raw) VALUES (?1, ?2, ?3, ?4, ?5)",
+            (
+                &picture.filename.to_str(),
+                &picture.mime,
+                picture.kind.to_i64(),
+                &picture.hash,
+                picture.raw,
+            ),
+        )?;
+        Ok(self.connection.last_insert_rowid() as u32)
+    }
+    /// Save the filename, thumbnail and missing mark of a picture.
+    pub fn update_picture(&self, picture: &picture::Picture) -> Result<(), LumenzaError> {
+        let thumbnail = match picture.thumbnail.as_os_str().is_empty() {
+            true => None,
+            false => picture.thumbnail.to_str(),
+        };
+        let changed = self.connection.execute(
+            "UPDATE pictures SET filename = ?1, thumbnail = ?2, missing = ?3 WHERE id = ?4",
+            (
+                picture.filename.to_str(),
+                thumbnail,
+                picture.missing,
+                picture.id,
+            ),
+        )?;
+        if changed == 0 {
+            return Err(LumenzaError::PictureNotFound());
+        }
+        Ok(())
+    }
+    /// Thumbnails of every picture, including missing ones.
+    pub fn list_thumbnails(&self) -> Result<Vec<PathBuf>, LumenzaError> {
+        let mut stmt = self.connection.prepare(
+            "SELECT thumbnail FROM pictures WHERE thumbnail IS NOT NULL
+            UNION SELECT path FROM thumbnails",
+        )?;
+        let thumbnails = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .map(|thumbnail| thumbnail.map(PathBuf::from))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(thumbnails)
+    }
+    /// Fill in the generated thumbnails of the pictures, smallest first.
+    fn read_thumbnails(&self, pictures: &mut [picture::Picture]) -> Result<(), LumenzaError> {
+        let mut stmt = self.connection.prepare_cached(
+            "SELECT size, crop, path FROM thumbnails WHERE picture = ?1 ORDER BY size, crop",
+        )?;
+        for picture in pictures {
+            picture.thumbnails = stmt
+                .query_map([picture.id], |row| {
+                    Ok((
+                        row.get(0)?,
+                        thumbnail::ThumbnailCrop::from_i64(row.get(1)?),
+                        PathBuf::from(row.get::<_, String>(2)?),
+                    ))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+        }
+        Ok(())
+    }
+    /// Replace the generated thumbnails of a picture, one per size and crop.
+    pub fn write_thumbnails(
+        &self,
+        picture: u32,
+        thumbnails: &[thumbnails::Thumbnail],
+    ) -> Result<(), LumenzaError> {
+        let tx = self.connection.unchecked_transaction()?;
+        tx.execute("DELETE FROM thumbnails WHERE picture = ?1", [picture])?;
+        for (size, crop, path) in thumbnails {
+            tx.execute(
+                "INSERT OR REPLACE INTO thumbnails (picture, size, crop, path)
+                VALUES (?1, ?2, ?3, ?4)",
+                (picture, size, crop.to_i64(), path.to_str()),
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+    pub fn set_picture_thumbnail(
+        &self,
+        picture: u32,
+        thumbnail: &Path,
+    ) -> Result<(), LumenzaError> {
+        self.connection.execute(
+            "UPDATE pictures SET thumbnail = ?1 WHERE id = ?2",
+            (thumbnail.to_str(), picture),
+        )?;
+        Ok(())
     }
     /// Search for a picture in the database
     pub fn lookup_picture(&self, picture: &picture::Picture) -> Result<bool, LumenzaError> {
         let mut stmt = self
             .connection
-            .prepare("SELECT filename FROM pictures WHERE filename = ?")
-            ?;
-        let mut rows = stmt.query(&[&picture.get_filename().to_str()])?;
+            .prepare("SELECT filename FROM pictures WHERE filename = ?")?;
+        let mut rows = stmt.query([&picture.get_filename().to_str()])?;
 
         // TODO: It returns on the first match, but it should check if there are more than one match.
         while let Some(row) = rows.next()? {
@@ -65,24 +263,1024 @@ impl Database {
         }
         Ok(false)
     }
-    pub fn list_all_pictures(&self) -> Result<Vec<picture::Picture>, LumenzaError> {
-        let mut pictures: Vec<picture::Picture> = Vec::new();
-        let mut stmt = self.connection.prepare("SELECT * FROM pictures")?;
-        let mut rows = stmt.query(())?;
-        while let Some(row) = rows.next()? {
+    pub fn get_picture_by_filename(
+        &self,
+        filename: &Path,
+    ) -> Result<Option<picture::Picture>, LumenzaError> {
+        let sql = format!(
+            "SELECT {} FROM pictures WHERE filename = ?1",
+            PICTURE_COLUMNS
+        );
+        let mut picture = self
+            .connection
+            .query_row(&sql, [filename.to_str()], picture_from_row)
+            .optional()?;
+        self.read_thumbnails(picture.as_mut_slice())?;
+        Ok(picture)
+    }
+    pub fn get_picture(&self, id: u32) -> Result<Option<picture::Picture>, LumenzaError> {
+        let sql = format!("SELECT {} FROM pictures WHERE id = ?1", PICTURE_COLUMNS);
+        let mut picture = self
+            .connection
+            .query_row(&sql, [id], picture_from_row)
+            .optional()?;
+        self.read_thumbnails(picture.as_mut_slice())?;
+        Ok(picture)
+    }
+    /// Run a query, returning the matching pictures in the requested order.
+    pub fn query_pictures(
+        &self,
+        query: &filter::Query,
+    ) -> Result<Vec<picture::Picture>, LumenzaError> {
+        let mut params = Vec::new();
+        let sql = format!(
+            "SELECT {} FROM pictures
+             LEFT JOIN picture_metadata ON picture_metadata.picture = pictures.id {}",
+            PICTURE_COLUMNS,
+            query.to_sql(&mut params)
+        );
+        let mut stmt = self.connection.prepare(&sql)?;
+        let mut pictures = stmt
+            .query_map(rusqlite::params_from_iter(params), picture_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        self.read_thumbnails(&mut pictures)?;
+        Ok(pictures)
+    }
+    /// Count the pictures matching the filter.
+    pub fn count_pictures(&self, filter: &filter::Filter) -> Result<u32, LumenzaError> {
+        let mut params = Vec::new();
+        let sql = format!(
+            "SELECT COUNT(*) FROM pictures
+             LEFT JOIN picture_metadata ON picture_metadata.picture = pictures.id
+             WHERE {} AND ({})",
+            filter::NOT_DETACHED,
+            filter.to_sql(&mut params)
+        );
+        let count = self
+            .connection
+            .query_row(&sql, rusqlite::params_from_iter(params), |row| row.get(0))?;
+        Ok(count)
+    }
+    pub fn set_picture_hash(&self, picture: u32, hash: &str) -> Result<(), LumenzaError> {
+        self.connection
+            .execute("UPDATE pictures SET hash = ?1 WHERE id = ?2", (hash, picture))?;
+        Ok(())
+    }
+    pub fn set_picture_filename(&self, picture: u32, filename: &Path) -> Result<(), LumenzaError> {
+        self.connection.execute(
+            "UPDATE pictures SET filename = ?1, missing = 0, detached = 0 WHERE id = ?2",
+            (filename.to_str(), picture),
+        )?;
+        Ok(())
+    }
+    pub fn set_picture_fingerprint(
+        &self,
+        picture: u32,
+        fingerprint: Option<hash::Fingerprint>,
+    ) -> Result<(), LumenzaError> {
+        let (size, mtime) = fingerprint.unzip();
+        self.connection.execute(
+            "UPDATE pictures SET size = ?1, mtime = ?2 WHERE id = ?3",
+            (size, mtime, picture),
+        )?;
+        Ok(())
+    }
+    pub fn get_picture_fingerprint(
+        &self,
+        picture: u32,
+    ) -> Result<Option<hash::Fingerprint>, LumenzaError> {
+        let fingerprint = self
+            .connection
+            .query_row(
+                "SELECT size, mtime FROM pictures WHERE id = ?1",
+                [picture],
+                |row| Ok(row.get::<_, Option<u64>>(0)?.zip(row.get(1)?)),
+            )
+            .optional()?;
+        Ok(fingerprint.flatten())
+    }
+    /// The id, fingerprint and missing mark of every picture, by filename.
+    pub fn list_fingerprints(&self) -> Result<HashMap<PathBuf, KnownFile>, LumenzaError> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT filename, id, size, mtime, missing FROM pictures")?;
+        let fingerprints = stmt
+            .query_map([], |row| {
+                let filename: String = row.get(0)?;
+                let size: Option<u64> = row.get(2)?;
+                let mtime: Option<i64> = row.get(3)?;
+                let fingerprint = size.zip(mtime);
+                Ok((
+                    PathBuf::from(filename),
+                    (row.get(1)?, fingerprint, row.get(4)?),
+                ))
+            })?
+            .collect::<Result<HashMap<_, _>, _>>()?;
+        Ok(fingerprints)
+    }
+    pub fn set_picture_missing(&self, picture: u32, missing: bool) -> Result<(), LumenzaError> {
+        self.connection.execute(
+            "UPDATE pictures SET missing = ?1, detached = 0 WHERE id = ?2",
+            (missing, picture),
+        )?;
+        Ok(())
+    }
+    /// Mark the picture as missing, but out of reach of verify() and
+    /// purge_missing_pictures() until it is found again by a scan.
+    pub fn detach_picture(&self, picture: u32) -> Result<(), LumenzaError> {
+        self.connection.execute(
+            "UPDATE pictures SET missing = 1, detached = 1 WHERE id = ?1",
+            [picture],
+        )?;
+        Ok(())
+    }
+    /// Clear the missing mark of the picture with the given filename. Returns
+    /// whether a picture was reattached.
+    pub fn reattach_picture(&self, filename: &Path) -> Result<bool, LumenzaError> {
+        let changed = self.connection.execute(
+            "UPDATE pictures SET missing = 0, detached = 0 WHERE filename = ?1 AND missing = 1",
+            [filename.to_str()],
+        )?;
+        Ok(changed > 0)
+    }
+    pub fn list_missing_pictures(&self) -> Result<Vec<picture::Picture>, LumenzaError> {
+        let sql = format!(
+            "SELECT {} FROM pictures WHERE missing = 1 AND detached = 0 ORDER BY id",
+            PICTURE_COLUMNS
+        );
+        let mut stmt = self.connection.prepare(&sql)?;
+        let mut pictures = stmt
+            .query_map([], picture_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        self.read_thumbnails(&mut pictures)?;
+        Ok(pictures)
+    }
+    /// Delete every picture marked as missing, along with its metadata, tags
+    /// and album entries.
+    pub fn purge_missing_pictures(&self) -> Result<Deletion, LumenzaError> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT id FROM pictures WHERE missing = 1 AND detached = 0")?;
+        let ids = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<u32>, _>>()?;
+        self.delete_pictures(&ids)
+    }
+    /// Delete the given pictures, along with their metadata, tags and album
+    /// entries. Albums they were in are renumbered, and their stacks are
+    /// deleted if a single picture is left.
+    pub fn delete_pictures(&self, ids: &[u32]) -> Result<Deletion, LumenzaError> {
+        let tx = self.connection.unchecked_transaction()?;
+        let (mut albums, mut stacks) = (BTreeSet::new(), BTreeSet::new());
+        {
+            let mut album_stmt =
+                tx.prepare("SELECT album FROM album_pictures WHERE picture = ?1")?;
+            let mut stack_stmt =
+                tx.prepare("SELECT stack FROM stack_pictures WHERE picture = ?1")?;
+            for id in ids {
+                for album in album_stmt.query_map([id], |row| row.get::<_, u32>(0))? {
+                    albums.insert(album?);
+                }
+                for stack in stack_stmt.query_map([id], |row| row.get::<_, u32>(0))? {
+                    stacks.insert(stack?);
+                }
+            }
+        }
+        let mut count = 0;
+        for id in ids {
+            count += tx.execute("DELETE FROM pictures WHERE id = ?1", [id])?;
+        }
+        for album in &albums {
+            let ids = self.list_album_picture_ids(*album)?;
+            write_album_positions(&tx, *album, &ids)?;
+        }
+        tx.execute(DELETE_SMALL_STACKS, [])?;
+        tx.commit()?;
+        Ok(Deletion {
+            count,
+            albums: albums.into_iter().collect(),
+            stacks: stacks.into_iter().collect(),
+        })
+    }
+    pub fn list_pictures_by_hash(&self, hash: &str) -> Result<Vec<picture::Picture>, LumenzaError> {
+        let sql = format!(
+            "SELECT {} FROM pictures WHERE hash = ?1 ORDER BY id",
+            PICTURE_COLUMNS
+        );
+        let mut stmt = self.connection.prepare(&sql)?;
+        let mut pictures = stmt
+            .query_map([hash], picture_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        self.read_thumbnails(&mut pictures)?;
+        Ok(pictures)
+    }
+    /// Pictures added before content hashes were stored.
+    pub fn list_unhashed_pictures(&self) -> Result<Vec<picture::Picture>, LumenzaError> {
+        let sql = format!(
+            "SELECT {} FROM pictures WHERE hash IS NULL",
+            PICTURE_COLUMNS
+        );
+        let mut stmt = self.connection.prepare(&sql)?;
+        let mut pictures = stmt
+            .query_map((), picture_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        self.read_thumbnails(&mut pictures)?;
+        Ok(pictures)
+    }
+    /// Pictures sharing their hash with at least one other picture, ordered
+    /// so that identical pictures are next to each other.
+    pub fn list_duplicate_pictures(&self) -> Result<Vec<picture::Picture>, LumenzaError> {
+        let sql = format!(
+            "SELECT {} FROM pictures WHERE missing = 0 AND hash IN (
+                SELECT hash FROM pictures WHERE hash IS NOT NULL AND missing = 0
+                GROUP BY hash HAVING COUNT(*) > 1
+            ) ORDER BY hash, id",
+            PICTURE_COLUMNS
+        );
+        let mut stmt = self.connection.prepare(&sql)?;
+        let mut pictures = stmt
+            .query_map((), picture_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        self.read_thumbnails(&mut pictures)?;
+        Ok(pictures)
+    }
+    pub fn get_rating(&self, picture: u32) -> Result<Option<u8>, LumenzaError> {
+        let rating = self
+            .connection
+            .query_row(
+                "SELECT rating FROM pictures WHERE id = ?1",
+                [picture],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(rating)
+    }
+    pub fn set_rating(&self, picture: u32, rating: u8) -> Result<(), LumenzaError> {
+        let changed = self.connection.execute(
+            "UPDATE pictures SET rating = ?1 WHERE id = ?2",
+            (rating, picture),
+        )?;
+        if changed == 0 {
+            return Err(LumenzaError::PictureNotFound());
+        }
+        Ok(())
+    }
+    /// List everything in the library, or only the given kind of media.
+    pub fn list_all_pictures(
+        &self,
+        kind: Option<media::MediaKind>,
+    ) -> Result<Vec<picture::Picture>, LumenzaError> {
+        let sql = format!(
+            "SELECT {} FROM pictures WHERE (?1 IS NULL OR kind = ?1) AND missing = 0",
+            PICTURE_COLUMNS
+        );
+        let mut stmt = self.connection.prepare(&sql)?;
+        let mut pictures = stmt
+            .query_map([kind.map(|kind| kind.to_i64())], picture_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        self.read_thumbnails(&mut pictures)?;
+        Ok(pictures)
+    }
+}
 
-            let id = row.get(0)?;
-            let filename: String = row.get(1)?;
-            let thumbnail: Option<String> = row.get(2).ok();
+// Metadata methods
+impl Database {
+    /// Insert or replace the metadata of a picture.
+    pub fn write_metadata(
+        &self,
+        picture: u32,
+        metadata: &metadata::Metadata,
+    ) -> Result<(), LumenzaError> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO picture_metadata (picture, date_taken, camera_make,
+                camera_model, lens, exposure_time, f_number, iso, focal_length, orientation,
+                latitude, longitude, width, height)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            rusqlite::params![
+                picture,
+                metadata.date_taken,
+                metadata.camera_make,
+                metadata.camera_model,
+                metadata.lens,
+                metadata.exposure_time,
+                metadata.f_number,
+                metadata.iso,
+                metadata.focal_length,
+                metadata.orientation,
+                metadata.latitude,
+                metadata.longitude,
+                metadata.width,
+                metadata.height,
+            ],
+        )?;
+        Ok(())
+    }
+    pub fn get_metadata(&self, picture: u32) -> Result<Option<metadata::Metadata>, LumenzaError> {
+        let metadata = self
+            .connection
+            .query_row(
+                "SELECT date_taken, camera_make, camera_model, lens, exposure_time, f_number,
+                    iso, focal_length, orientation, latitude, longitude, width, height
+                 FROM picture_metadata WHERE picture = ?1",
+                [picture],
+                |row| {
+                    Ok(metadata::Metadata {
+                        date_taken: row.get(0)?,
+                        camera_make: row.get(1)?,
+                        camera_model: row.get(2)?,
+                        lens: row.get(3)?,
+                        exposure_time: row.get(4)?,
+                        f_number: row.get(5)?,
+                        iso: row.get(6)?,
+                        focal_length: row.get(7)?,
+                        orientation: row.get(8)?,
+                        latitude: row.get(9)?,
+                        longitude: row.get(10)?,
+                        width: row.get(11)?,
+                        height: row.get(12)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(metadata)
+    }
+}
 
-        
-            let picture = picture::Picture {
-                _id: id,
-                filename: PathBuf::from(filename),
-                thumbnail: thumbnail.map(PathBuf::from).unwrap_or_default(),
-            };
-            pictures.push(picture);
+// Video methods
+impl Database {
+    pub fn write_video_info(
+        &self,
+        picture: u32,
+        info: &media::VideoInfo,
+    ) -> Result<(), LumenzaError> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO video_metadata (picture, duration, width, height,
+                creation_time, codec)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            (
+                picture,
+                info.duration,
+                info.width,
+                info.height,
+                info.creation_time,
+                &info.codec,
+            ),
+        )?;
+        Ok(())
+    }
+    /// Every picture and video that is not missing, videos along with their
+    /// container metadata.
+    pub fn list_all_media(&self) -> Result<Vec<media::Media>, LumenzaError> {
+        let sql = format!(
+            "SELECT {}, video_metadata.duration, video_metadata.width, video_metadata.height,
+                video_metadata.creation_time, video_metadata.codec
+            FROM pictures LEFT JOIN video_metadata ON video_metadata.picture = pictures.id
+            WHERE missing = 0",
+            PICTURE_COLUMNS
+        );
+        let mut stmt = self.connection.prepare(&sql)?;
+        let mut media = stmt
+            .query_map([], media_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        for item in &mut media {
+            let (media::Media::Picture(picture) | media::Media::Video(picture, _)) = item;
+            self.read_thumbnails(std::slice::from_mut(picture))?;
         }
+        Ok(media)
+    }
+    pub fn get_video_info(&self, picture: u32) -> Result<Option<media::VideoInfo>, LumenzaError> {
+        let info = self
+            .connection
+            .query_row(
+                "SELECT duration, width, height, creation_time, codec
+                 FROM video_metadata WHERE picture = ?1",
+                [picture],
+                |row| {
+                    Ok(media::VideoInfo {
+                        duration: row.get(0)?,
+                        width: row.get(1)?,
+                        height: row.get(2)?,
+                        creation_time: row.get(3)?,
+                        codec: row.get(4)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(info)
+    }
+}
+
+// Live Photo methods
+impl Database {
+    pub fn write_content_identifier(&self, picture: u32, id: &str) -> Result<(), LumenzaError> {
+        self.connection.execute(
+            "UPDATE pictures SET content_id = ?1 WHERE id = ?2",
+            (id, picture),
+        )?;
+        Ok(())
+    }
+    pub fn write_embedded_video(
+        &self,
+        picture: u32,
+        offset: u64,
+        length: u64,
+    ) -> Result<(), LumenzaError> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO live_photos (picture, video_offset, video_length)
+             VALUES (?1, ?2, ?3)",
+            (picture, offset, length),
+        )?;
+        Ok(())
+    }
+    /// Pair the stills and videos sharing a content identifier that are not
+    /// paired yet. Returns the number of pairs made.
+    pub fn pair_live_photos(&self) -> Result<usize, LumenzaError> {
+        let paired = self.connection.execute(
+            "INSERT OR IGNORE INTO live_photos (picture, video)
+             SELECT still.id, video.id FROM pictures AS still
+             JOIN pictures AS video ON video.content_id = still.content_id
+             WHERE still.kind = ?1 AND video.kind = ?2
+             AND still.id NOT IN (SELECT picture FROM live_photos)
+             AND video.id NOT IN (SELECT video FROM live_photos WHERE video IS NOT NULL)
+             ORDER BY still.id, video.id",
+            (
+                media::MediaKind::Picture.to_i64(),
+                media::MediaKind::Video.to_i64(),
+            ),
+        )?;
+        Ok(paired)
+    }
+    pub fn get_live_video(&self, picture: u32) -> Result<Option<media::LiveVideo>, LumenzaError> {
+        let row = self
+            .connection
+            .query_row(
+                "SELECT video, video_offset, video_length FROM live_photos WHERE picture = ?1",
+                [picture],
+                |row| {
+                    Ok((
+                        row.get::<_, Option<u32>>(0)?,
+                        row.get::<_, Option<u64>>(1)?,
+                        row.get::<_, Option<u64>>(2)?,
+                    ))
+                },
+            )
+            .optional()?;
+        match row {
+            Some((Some(video), _, _)) => {
+                Ok(self.get_picture(video)?.map(media::LiveVideo::Paired))
+            }
+            Some((None, Some(offset), Some(length))) => {
+                Ok(Some(media::LiveVideo::Embedded { offset, length }))
+            }
+            _ => Ok(None),
+        }
+    }
+    /// The still a video is paired with.
+    pub fn get_live_still(&self, video: u32) -> Result<Option<picture::Picture>, LumenzaError> {
+        let sql = format!(
+            "SELECT {} FROM pictures JOIN live_photos ON live_photos.picture = pictures.id
+             WHERE live_photos.video = ?1",
+            PICTURE_COLUMNS
+        );
+        let mut picture = self
+            .connection
+            .query_row(&sql, [video], picture_from_row)
+            .optional()?;
+        self.read_thumbnails(picture.as_mut_slice())?;
+        Ok(picture)
+    }
+}
+
+// Album methods
+impl Database {
+    pub fn write_album(&self, name: &str) -> Result<u32, LumenzaError> {
+        self.connection
+            .execute("INSERT INTO albums (name) VALUES (?1)", (name,))?;
+        Ok(self.connection.last_insert_rowid() as u32)
+    }
+    pub fn get_album(&self, id: u32) -> Result<Option<album::Album>, LumenzaError> {
+        let album = self
+            .connection
+            .query_row(
+                "SELECT albums.id, albums.name, albums.cover, COUNT(album_pictures.picture)
+                 FROM albums LEFT JOIN album_pictures ON album_pictures.album = albums.id
+                 WHERE albums.id = ?1 GROUP BY albums.id",
+                [id],
+                album_from_row,
+            )
+            .optional()?;
+        Ok(album)
+    }
+    pub fn list_albums(&self) -> Result<Vec<album::Album>, LumenzaError> {
+        let mut stmt = self.connection.prepare(
+            "SELECT albums.id, albums.name, albums.cover, COUNT(album_pictures.picture)
+             FROM albums LEFT JOIN album_pictures ON album_pictures.album = albums.id
+             GROUP BY albums.id ORDER BY albums.name, albums.id",
+        )?;
+        let albums = stmt
+            .query_map((), album_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(albums)
+    }
+    pub fn rename_album(&self, id: u32, name: &str) -> Result<(), LumenzaError> {
+        let changed = self
+            .connection
+            .execute("UPDATE albums SET name = ?1 WHERE id = ?2", (name, id))?;
+        if changed == 0 {
+            return Err(LumenzaError::AlbumNotFound());
+        }
+        Ok(())
+    }
+    pub fn delete_album(&self, id: u32) -> Result<(), LumenzaError> {
+        let changed = self
+            .connection
+            .execute("DELETE FROM albums WHERE id = ?1", [id])?;
+        if changed == 0 {
+            return Err(LumenzaError::AlbumNotFound());
+        }
+        Ok(())
+    }
+    pub fn set_album_cover(&self, id: u32, cover: Option<u32>) -> Result<(), LumenzaError> {
+        let changed = self
+            .connection
+            .execute("UPDATE albums SET cover = ?1 WHERE id = ?2", (cover, id))?;
+        if changed == 0 {
+            return Err(LumenzaError::AlbumNotFound());
+        }
+        Ok(())
+    }
+    /// Ids of the pictures in the album, in album order.
+    pub fn list_album_picture_ids(&self, album: u32) -> Result<Vec<u32>, LumenzaError> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT picture FROM album_pictures WHERE album = ?1 ORDER BY position")?;
+        let ids = stmt
+            .query_map([album], |row| row.get(0))?
+            .collect::<Result<Vec<u32>, _>>()?;
+        Ok(ids)
+    }
+    pub fn list_album_pictures(&self, album: u32) -> Result<Vec<picture::Picture>, LumenzaError> {
+        let sql = format!(
+            "SELECT {} FROM pictures JOIN album_pictures ON album_pictures.picture = pictures.id
+             WHERE album_pictures.album = ?1 ORDER BY album_pictures.position",
+            PICTURE_COLUMNS
+        );
+        let mut stmt = self.connection.prepare(&sql)?;
+        let mut pictures = stmt
+            .query_map([album], picture_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        self.read_thumbnails(&mut pictures)?;
+        Ok(pictures)
+    }
+    /// Append a picture to the end of the album.
+    pub fn add_album_picture(&self, album: u32, picture: u32) -> Result<(), LumenzaError> {
+        if self.list_album_picture_ids(album)?.contains(&picture) {
+            return Err(LumenzaError::PictureAlreadyInAlbum());
+        }
+        self.connection.execute(
+            "INSERT INTO album_pictures (album, picture, position)
+             VALUES (?1, ?2, (SELECT COALESCE(MAX(position) + 1, 0) FROM album_pictures WHERE album = ?1))",
+            (album, picture),
+        )?;
+        Ok(())
+    }
+    pub fn remove_album_picture(&self, album: u32, picture: u32) -> Result<(), LumenzaError> {
+        let mut ids = self.list_album_picture_ids(album)?;
+        let index = ids
+            .iter()
+            .position(|id| *id == picture)
+            .ok_or(LumenzaError::PictureNotInAlbum())?;
+        ids.remove(index);
+
+        let tx = self.connection.unchecked_transaction()?;
+        tx.execute(
+            "DELETE FROM album_pictures WHERE album = ?1 AND picture = ?2",
+            (album, picture),
+        )?;
+        tx.execute(
+            "UPDATE albums SET cover = NULL WHERE id = ?1 AND cover = ?2",
+            (album, picture),
+        )?;
+        write_album_positions(&tx, album, &ids)?;
+        tx.commit()?;
+        Ok(())
+    }
+    /// Move a picture to the given position in the album. Positions past the
+    /// end of the album move the picture to the end.
+    pub fn move_album_picture(
+        &self,
+        album: u32,
+        picture: u32,
+        position: usize,
+    ) -> Result<(), LumenzaError> {
+        let mut ids = self.list_album_picture_ids(album)?;
+        let index = ids
+            .iter()
+            .position(|id| *id == picture)
+            .ok_or(LumenzaError::PictureNotInAlbum())?;
+        let id = ids.remove(index);
+        ids.insert(position.min(ids.len()), id);
+
+        let tx = self.connection.unchecked_transaction()?;
+        write_album_positions(&tx, album, &ids)?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+// Stack methods
+impl Database {
+    /// Stack the pictures, which must not be in a stack already. The top
+    /// must be one of them.
+    pub fn write_stack(
+        &self,
+        kind: stack::StackKind,
+        top: u32,
+        pictures: &[u32],
+    ) -> Result<u32, LumenzaError> {
+        let tx = self.connection.unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO stacks (kind, top) VALUES (?1, ?2)",
+            (kind.to_i64(), top),
+        )?;
+        let id = tx.last_insert_rowid() as u32;
+        for picture in pictures {
+            let stacked = tx.execute(
+                "INSERT OR IGNORE INTO stack_pictures (stack, picture) VALUES (?1, ?2)",
+                (id, picture),
+            )?;
+            if stacked == 0 {
+                return Err(LumenzaError::PictureAlreadyInStack());
+            }
+        }
+        tx.commit()?;
+        Ok(id)
+    }
+    pub fn get_stack(&self, id: u32) -> Result<Option<stack::Stack>, LumenzaError> {
+        let sql = format!("{} WHERE stacks.id = ?1 GROUP BY stacks.id", stack_select());
+        let stack = self
+            .connection
+            .query_row(&sql, [id], stack_from_row)
+            .optional()?;
+        Ok(stack)
+    }
+    /// The stack the picture is in, if any.
+    pub fn get_picture_stack(&self, picture: u32) -> Result<Option<stack::Stack>, LumenzaError> {
+        let sql = format!(
+            "{} WHERE stacks.id = (SELECT stack FROM stack_pictures WHERE picture = ?1)
+             GROUP BY stacks.id",
+            stack_select()
+        );
+        let stack = self
+            .connection
+            .query_row(&sql, [picture], stack_from_row)
+            .optional()?;
+        Ok(stack)
+    }
+    pub fn list_stacks(&self) -> Result<Vec<stack::Stack>, LumenzaError> {
+        let sql = format!("{} GROUP BY stacks.id ORDER BY stacks.id", stack_select());
+        let mut stmt = self.connection.prepare(&sql)?;
+        let stacks = stmt
+            .query_map((), stack_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(stacks)
+    }
+    /// Pictures of the stack, its top first.
+    pub fn list_stack_pictures(&self, stack: u32) -> Result<Vec<picture::Picture>, LumenzaError> {
+        let sql = format!(
+            "SELECT {} FROM pictures JOIN stack_pictures ON stack_pictures.picture = pictures.id
+             JOIN stacks ON stacks.id = stack_pictures.stack
+             WHERE stacks.id = ?1 ORDER BY pictures.id != {}, pictures.id",
+            PICTURE_COLUMNS,
+            filter::STACK_TOP
+        );
+        let mut stmt = self.connection.prepare(&sql)?;
+        let mut pictures = stmt
+            .query_map([stack], picture_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        self.read_thumbnails(&mut pictures)?;
         Ok(pictures)
     }
+    pub fn set_stack_top(&self, stack: u32, picture: u32) -> Result<(), LumenzaError> {
+        let changed = self.connection.execute(
+            "UPDATE stacks SET top = ?2 WHERE id = ?1
+             AND ?2 IN (SELECT picture FROM stack_pictures WHERE stack = ?1)",
+            (stack, picture),
+        )?;
+        if changed == 0 {
+            return Err(LumenzaError::PictureNotInStack());
+        }
+        Ok(())
+    }
+    /// Take the picture out of the stack, deleting the stack if a single
+    /// picture is left. Returns whether the stack was deleted.
+    pub fn remove_stack_picture(&self, stack: u32, picture: u32) -> Result<bool, LumenzaError> {
+        let tx = self.connection.unchecked_transaction()?;
+        let removed = tx.execute(
+            "DELETE FROM stack_pictures WHERE stack = ?1 AND picture = ?2",
+            (stack, picture),
+        )?;
+        if removed == 0 {
+            return Err(LumenzaError::PictureNotInStack());
+        }
+        tx.execute(
+            "UPDATE stacks SET top = NULL WHERE id = ?1 AND top = ?2",
+            (stack, picture),
+        )?;
+        let deleted = tx.execute(DELETE_SMALL_STACKS, [])?;
+        tx.commit()?;
+        Ok(deleted > 0)
+    }
+    pub fn delete_stack(&self, id: u32) -> Result<(), LumenzaError> {
+        let changed = self
+            .connection
+            .execute("DELETE FROM stacks WHERE id = ?1", [id])?;
+        if changed == 0 {
+            return Err(LumenzaError::StackNotFound());
+        }
+        Ok(())
+    }
+    /// Pictures that are not stacked, along with their capture date. Missing
+    /// pictures are left out.
+    pub fn list_unstacked_pictures(
+        &self,
+    ) -> Result<Vec<(picture::Picture, Option<NaiveDateTime>)>, LumenzaError> {
+        let sql = format!(
+            "SELECT {}, picture_metadata.date_taken FROM pictures
+             LEFT JOIN picture_metadata ON picture_metadata.picture = pictures.id
+             WHERE pictures.missing = 0
+             AND pictures.id NOT IN (SELECT picture FROM stack_pictures)
+             ORDER BY pictures.id",
+            PICTURE_COLUMNS
+        );
+        let mut stmt = self.connection.prepare(&sql)?;
+        let mut pictures: Vec<stack::Candidate> = stmt
+            .query_map((), |row| Ok((picture_from_row(row)?, row.get(8)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        for (picture, _) in &mut pictures {
+            self.read_thumbnails(std::slice::from_mut(picture))?;
+        }
+        Ok(pictures)
+    }
+}
+
+// Tag methods
+impl Database {
+    pub fn write_tag(&self, parent: Option<u32>, name: &str) -> Result<u32, LumenzaError> {
+        self.connection.execute(
+            "INSERT INTO tags (name, parent) VALUES (?1, ?2)",
+            (name, parent),
+        )?;
+        Ok(self.connection.last_insert_rowid() as u32)
+    }
+    /// Find the child of `parent` (or the top level tag) with the given name.
+    pub fn find_tag(&self, parent: Option<u32>, name: &str) -> Result<Option<u32>, LumenzaError> {
+        let id = self
+            .connection
+            .query_row(
+                "SELECT id FROM tags WHERE parent IS ?1 AND name = ?2",
+                (parent, name),
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(id)
+    }
+    pub fn get_tag(&self, id: u32) -> Result<Option<tag::Tag>, LumenzaError> {
+        let sql = format!(
+            "{} SELECT id, name, path, parent FROM tag_paths WHERE id = ?1",
+            TAG_PATHS
+        );
+        let tag = self
+            .connection
+            .query_row(&sql, [id], tag_from_row)
+            .optional()?;
+        Ok(tag)
+    }
+    pub fn list_tags(&self) -> Result<Vec<tag::Tag>, LumenzaError> {
+        let sql = format!(
+            "{} SELECT id, name, path, parent FROM tag_paths ORDER BY path",
+            TAG_PATHS
+        );
+        let mut stmt = self.connection.prepare(&sql)?;
+        let tags = stmt
+            .query_map((), tag_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(tags)
+    }
+    pub fn rename_tag(&self, id: u32, name: &str) -> Result<(), LumenzaError> {
+        let changed = self
+            .connection
+            .execute("UPDATE tags SET name = ?1 WHERE id = ?2", (name, id))?;
+        if changed == 0 {
+            return Err(LumenzaError::TagNotFound());
+        }
+        Ok(())
+    }
+    /// Delete the tag, along with all of its descendants and assignments.
+    pub fn delete_tag(&self, id: u32) -> Result<(), LumenzaError> {
+        let changed = self
+            .connection
+            .execute("DELETE FROM tags WHERE id = ?1", [id])?;
+        if changed == 0 {
+            return Err(LumenzaError::TagNotFound());
+        }
+        Ok(())
+    }
+    /// Merge `source` into `target`. Pictures tagged with `source` are tagged
+    /// with `target` instead, and children of `source` are moved under
+    /// `target`, merging them with children of the same name.
+    pub fn merge_tag(&self, source: u32, target: u32) -> Result<(), LumenzaError> {
+        let tx = self.connection.unchecked_transaction()?;
+        merge_tag_into(&tx, source, target)?;
+        tx.commit()?;
+        Ok(())
+    }
+    /// Ids of the tag and all of its descendants.
+    pub fn list_tag_subtree(&self, id: u32) -> Result<Vec<u32>, LumenzaError> {
+        let mut stmt = self.connection.prepare(
+            "WITH RECURSIVE subtree(id) AS (
+                SELECT ?1 UNION SELECT tags.id FROM tags JOIN subtree ON tags.parent = subtree.id
+            ) SELECT id FROM subtree",
+        )?;
+        let ids = stmt
+            .query_map([id], |row| row.get(0))?
+            .collect::<Result<Vec<u32>, _>>()?;
+        Ok(ids)
+    }
+    pub fn tag_picture(&self, picture: u32, tag: u32) -> Result<(), LumenzaError> {
+        self.connection.execute(
+            "INSERT OR IGNORE INTO picture_tags (picture, tag) VALUES (?1, ?2)",
+            (picture, tag),
+        )?;
+        Ok(())
+    }
+    pub fn untag_picture(&self, picture: u32, tag: u32) -> Result<(), LumenzaError> {
+        self.connection.execute(
+            "DELETE FROM picture_tags WHERE picture = ?1 AND tag = ?2",
+            (picture, tag),
+        )?;
+        Ok(())
+    }
+    pub fn list_picture_tags(&self, picture: u32) -> Result<Vec<tag::Tag>, LumenzaError> {
+        let sql = format!(
+            "{} SELECT tag_paths.id, tag_paths.name, tag_paths.path, tag_paths.parent
+             FROM tag_paths JOIN picture_tags ON picture_tags.tag = tag_paths.id
+             WHERE picture_tags.picture = ?1 ORDER BY tag_paths.path",
+            TAG_PATHS
+        );
+        let mut stmt = self.connection.prepare(&sql)?;
+        let tags = stmt
+            .query_map([picture], tag_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(tags)
+    }
+    /// Pictures tagged with the tag, or with any of its descendants if
+    /// `descendants` is set.
+    pub fn list_tagged_pictures(
+        &self,
+        tag: u32,
+        descendants: bool,
+    ) -> Result<Vec<picture::Picture>, LumenzaError> {
+        let sql = format!(
+            "WITH RECURSIVE subtree(id) AS (
+                SELECT ?1 UNION SELECT tags.id FROM tags JOIN subtree ON tags.parent = subtree.id AND ?2
+            )
+            SELECT {} FROM pictures WHERE pictures.id IN (
+                SELECT picture_tags.picture FROM picture_tags JOIN subtree ON picture_tags.tag = subtree.id
+            ) ORDER BY pictures.id",
+            PICTURE_COLUMNS
+        );
+        let mut stmt = self.connection.prepare(&sql)?;
+        let mut pictures = stmt
+            .query_map((tag, descendants), picture_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        self.read_thumbnails(&mut pictures)?;
+        Ok(pictures)
+    }
+}
+
+/// Build a picture or video from a row selected with PICTURE_COLUMNS, followed
+/// by the columns of `video_metadata`.
+fn media_from_row(row: &Row) -> rusqlite::Result<media::Media> {
+    let picture = picture_from_row(row)?;
+    if picture.kind != media::MediaKind::Video {
+        return Ok(media::Media::Picture(picture));
+    }
+    let info = media::VideoInfo {
+        duration: row.get("duration")?,
+        width: row.get("width")?,
+        height: row.get("height")?,
+        creation_time: row.get("creation_time")?,
+        codec: row.get("codec")?,
+    };
+    Ok(media::Media::Video(picture, info))
+}
+
+/// Build a picture from a row selected with PICTURE_COLUMNS.
+fn picture_from_row(row: &Row) -> rusqlite::Result<picture::Picture> {
+    let id = row.get(0)?;
+    let filename: String = row.get(1)?;
+    let thumbnail: Option<String> = row.get(2)?;
+    let mime: Option<String> = row.get(3)?;
+    let kind: i64 = row.get(4)?;
+    let hash: Option<String> = row.get(5)?;
+    let missing: bool = row.get(6)?;
+    let raw: bool = row.get(7)?;
+
+    Ok(picture::Picture {
+        id,
+        filename: PathBuf::from(filename),
+        thumbnail: thumbnail.map(PathBuf::from).unwrap_or_default(),
+        mime: mime.unwrap_or_default(),
+        kind: media::MediaKind::from_i64(kind),
+        raw,
+        hash,
+        missing,
+        // Filled in by Database::read_thumbnails().
+        thumbnails: Vec::new(),
+    })
+}
+
+fn album_from_row(row: &Row) -> rusqlite::Result<album::Album> {
+    Ok(album::Album {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        cover: row.get(2)?,
+        picture_count: row.get(3)?,
+    })
+}
+
+/// Select every stack, in the order expected by stack_from_row(). Filtered
+/// with a WHERE clause on `stacks` placed before GROUP BY.
+fn stack_select() -> String {
+    format!(
+        "SELECT stacks.id, stacks.kind, {}, COUNT(stack_pictures.picture)
+         FROM stacks LEFT JOIN stack_pictures ON stack_pictures.stack = stacks.id",
+        filter::STACK_TOP
+    )
+}
+
+fn stack_from_row(row: &Row) -> rusqlite::Result<stack::Stack> {
+    let kind: i64 = row.get(1)?;
+    Ok(stack::Stack {
+        id: row.get(0)?,
+        kind: stack::StackKind::from_i64(kind),
+        top: row.get(2)?,
+        picture_count: row.get(3)?,
+    })
+}
+
+fn tag_from_row(row: &Row) -> rusqlite::Result<tag::Tag> {
+    Ok(tag::Tag {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        path: row.get(2)?,
+        parent: row.get(3)?,
+    })
+}
+
+fn merge_tag_into(connection: &Connection, source: u32, target: u32) -> Result<(), LumenzaError> {
+    connection.execute(
+        "INSERT OR IGNORE INTO picture_tags (picture, tag)
+         SELECT picture, ?2 FROM picture_tags WHERE tag = ?1",
+        (source, target),
+    )?;
+
+    // Children are collected first, as they are modified while merging.
+    let mut stmt = connection.prepare("SELECT id, name FROM tags WHERE parent = ?1")?;
+    let children = stmt
+        .query_map([source], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<Vec<(u32, String)>, _>>()?;
+    for (child, name) in children {
+        let existing: Option<u32> = connection
+            .query_row(
+                "SELECT id FROM tags WHERE parent = ?1 AND name = ?2",
+                (target, &name),
+                |row| row.get(0),
+            )
+            .optional()?;
+        match existing {
+            Some(existing) => merge_tag_into(connection, child, existing)?,
+            None => {
+                connection.execute("UPDATE tags SET parent = ?1 WHERE id = ?2", (target, child))?;
+            }
+        }
+    }
+
+    connection.execute("DELETE FROM tags WHERE id = ?1", [source])?;
+    Ok(())
+}
+
+/// Rewrite the positions of an album so that they match the order of `ids`.
+fn write_album_positions(
+    connection: &Connection,
+    album: u32,
+    ids: &[u32],
+) -> Result<(), LumenzaError> {
+    let mut stmt = connection
+        .prepare("UPDATE album_pictures SET position = ?1 WHERE album = ?2 AND picture = ?3")?;
+    for (position, id) in ids.iter().enumerate() {
+        stmt.execute((position as u32, album, id))?;
+    }
+    Ok(())
 }
@@ -0,0 +1,32 @@
+use crate::error::LumenzaError;
+
+use std::fs::File;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+/// Size of a file in bytes, and its modification time in nanoseconds since
+/// the Unix epoch.
+pub type Fingerprint = (u64, i64);
+
+/// Length of the hex strings given by hash_file().
+pub const HASH_LENGTH: usize = 2 * blake3::OUT_LEN;
+
+/// Compute the BLAKE3 hash of a file's content, as a hex string. Identical
+/// files give identical hashes, wherever they are stored and whatever their
+/// name.
+pub fn hash_file(path: &Path) -> Result<String, LumenzaError> {
+    let mut file = File::open(path).map_err(|_| LumenzaError::FileNotFound())?;
+    let mut hasher = blake3::Hasher::new();
+    hasher
+        .update_reader(&mut file)
+        .map_err(|_| LumenzaError::IoError())?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Get the fingerprint of a file, which changes whenever the file is
+/// written to. Much cheaper than hashing it, as the file is not read.
+pub fn fingerprint(path: &Path) -> Option<Fingerprint> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((metadata.len(), mtime.as_nanos() as i64))
+}
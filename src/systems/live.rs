@@ -0,0 +1,185 @@
+use exif::{In, Tag, Value};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::media::MediaKind;
+use crate::metadata;
+use crate::systems::container;
+
+/// Apple maker notes start with this header, followed by a version and a
+/// TIFF byte order mark.
+const APPLE_MAKER_NOTE: &[u8] = b"Apple iOS\0";
+/// Offset of the first IFD of Apple maker notes.
+const APPLE_IFD_OFFSET: usize = 14;
+/// Maker note tag holding the content identifier of a Live Photo still.
+const APPLE_CONTENT_IDENTIFIER: u16 = 0x0011;
+/// QuickTime metadata key holding the content identifier of a Live Photo
+/// video.
+const QUICKTIME_CONTENT_IDENTIFIER: &[u8] = b"com.apple.quicktime.content.identifier";
+
+/// Largest JPEG header searched for XMP data.
+const XMP_HEADER_SIZE: u64 = 256 * 1024;
+const XMP_NAMESPACE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+/// Read the identifier shared by the still and the video of an Apple Live
+/// Photo. The still keeps it in its maker note, the video in its QuickTime
+/// metadata.
+pub fn content_identifier(path: &Path, kind: MediaKind) -> Option<String> {
+    match kind {
+        MediaKind::Picture => {
+            let exif = metadata::read_exif(path)?;
+            let field = exif.get_field(Tag::MakerNote, In::PRIMARY)?;
+            match &field.value {
+                Value::Undefined(note, _) => apple_content_identifier(note),
+                _ => None,
+            }
+        }
+        MediaKind::Video => {
+            let file = File::open(path).ok()?;
+            let moov = container::read_moov(&mut BufReader::new(file))?;
+            quicktime_content_identifier(&moov)
+        }
+    }
+}
+
+/// Find the MP4 video appended to an Android motion photo, giving its offset
+/// and length in the file. Its XMP data tells how long the video is.
+pub fn embedded_video(path: &Path) -> Option<(u64, u64)> {
+    let mut file = File::open(path).ok()?;
+    let mut header = Vec::new();
+    (&mut file)
+        .take(XMP_HEADER_SIZE)
+        .read_to_end(&mut header)
+        .ok()?;
+    if !header.starts_with(&[0xff, 0xd8]) {
+        return None;
+    }
+    let xmp = String::from_utf8_lossy(xmp_segment(&header)?).into_owned();
+
+    // Older phones give the offset of the video from the end of the file,
+    // newer ones list it as an item of the container.
+    let length = match xmp_value(&xmp, "GCamera:MicroVideoOffset") {
+        Some(length) => length,
+        None => {
+            let start = xmp.find("Item:Semantic=\"MotionPhoto\"")?;
+            let item = xmp[..start].rfind('<').unwrap_or(0);
+            let end = xmp[start..].find('>').map_or(xmp.len(), |end| start + end);
+            xmp_value(&xmp[item..end], "Item:Length")?
+        }
+    };
+
+    let file_length = file.seek(SeekFrom::End(0)).ok()?;
+    let offset = file_length.checked_sub(length)?;
+    let mut kind = [0; 8];
+    file.seek(SeekFrom::Start(offset)).ok()?;
+    file.read_exact(&mut kind).ok()?;
+    match &kind[4..] {
+        b"ftyp" => Some((offset, length)),
+        _ => None,
+    }
+}
+
+/// The content identifier in an Apple maker note.
+fn apple_content_identifier(note: &[u8]) -> Option<String> {
+    if !note.starts_with(APPLE_MAKER_NOTE) {
+        return None;
+    }
+    let big_endian = note.get(12..14)? == b"MM";
+    let u16_at = |offset: usize| -> Option<u16> {
+        let bytes = note.get(offset..offset + 2)?.try_into().ok()?;
+        Some(match big_endian {
+            true => u16::from_be_bytes(bytes),
+            false => u16::from_le_bytes(bytes),
+        })
+    };
+    let u32_at = |offset: usize| -> Option<u32> {
+        let bytes = note.get(offset..offset + 4)?.try_into().ok()?;
+        Some(match big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        })
+    };
+
+    let count = u16_at(APPLE_IFD_OFFSET)? as usize;
+    for index in 0..count {
+        let entry = APPLE_IFD_OFFSET + 2 + 12 * index;
+        if u16_at(entry)? != APPLE_CONTENT_IDENTIFIER {
+            continue;
+        }
+        // Offsets are relative to the start of the maker note.
+        let length = u32_at(entry + 4)? as usize;
+        let start = match length <= 4 {
+            true => entry + 8,
+            false => u32_at(entry + 8)? as usize,
+        };
+        let value = note.get(start..start.checked_add(length)?)?;
+        let value = String::from_utf8_lossy(value);
+        return Some(value.trim_end_matches('\0').to_string());
+    }
+    None
+}
+
+/// The content identifier in the `moov/meta` box of a QuickTime file, which
+/// lists metadata keys in `keys` and their values in `ilst`.
+fn quicktime_content_identifier(moov: &[u8]) -> Option<String> {
+    let meta = container::find_box(moov, b"meta")?;
+    // QuickTime meta boxes have no version, unlike the ISO ones.
+    let meta = match container::find_box(meta, b"keys") {
+        Some(_) => meta,
+        None => meta.get(4..)?,
+    };
+    let keys = container::find_box(meta, b"keys")?;
+
+    let count = u32::from_be_bytes(keys.get(4..8)?.try_into().ok()?);
+    let mut offset = 8;
+    let mut index = None;
+    for key in 1..=count {
+        let size = u32::from_be_bytes(keys.get(offset..offset + 4)?.try_into().ok()?) as usize;
+        if keys.get(offset + 8..offset + size)? == QUICKTIME_CONTENT_IDENTIFIER {
+            index = Some(key);
+            break;
+        }
+        offset += size.max(8);
+    }
+
+    // Values are boxes named after the index of their key.
+    let ilst = container::find_box(meta, b"ilst")?;
+    let item = container::find_box(ilst, &index?.to_be_bytes())?;
+    let data = container::find_box(item, b"data")?;
+    Some(String::from_utf8_lossy(data.get(8..)?).to_string())
+}
+
+/// The XMP packet in the APP1 segment of a JPEG.
+fn xmp_segment(jpeg: &[u8]) -> Option<&[u8]> {
+    let mut offset = 2;
+    while offset + 4 <= jpeg.len() && jpeg[offset] == 0xff {
+        let marker = jpeg[offset + 1];
+        let length = u16::from_be_bytes([jpeg[offset + 2], jpeg[offset + 3]]) as usize;
+        let segment = jpeg.get(offset + 4..offset + 2 + length)?;
+        if marker == 0xe1 && segment.starts_with(XMP_NAMESPACE) {
+            return Some(&segment[XMP_NAMESPACE.len()..]);
+        }
+        if marker == 0xda {
+            return None;
+        }
+        offset += 2 + length;
+    }
+    None
+}
+
+/// Read a number from XMP data, written either as an attribute or as an
+/// element.
+fn xmp_value(xmp: &str, name: &str) -> Option<u64> {
+    let attribute = format!("{}=\"", name);
+    let element = format!("<{}>", name);
+    let start = match xmp.find(&attribute) {
+        Some(start) => start + attribute.len(),
+        None => xmp.find(&element)? + element.len(),
+    };
+    let value: String = xmp[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    value.parse().ok().filter(|&value| value > 0)
+}
@@ -1,2 +1,7 @@
 pub(crate) mod database;
+pub(crate) mod hash;
 pub(crate) mod config;
+pub(crate) mod container;
+pub(crate) mod live;
+pub(crate) mod preview;
+pub(crate) mod thumbnails;
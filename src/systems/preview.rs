@@ -0,0 +1,362 @@
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// A JPEG preview embedded in a picture
+///
+/// Only its location and size are read by read(), its data is read by
+/// load() once a preview is chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Preview {
+    /// Position of the JPEG data in the file.
+    pub offset: u64,
+    pub length: u64,
+    /// Size of the preview according to its JPEG header, before the EXIF
+    /// orientation of the picture is applied.
+    pub width: u32,
+    pub height: u32,
+}
+
+/// JPEG previews embedded in the metadata of a picture
+///
+/// Cameras store a small preview in the EXIF data of JPEGs, and usually a
+/// full size one in RAW files, which are mostly TIFF based. Decoding them is
+/// much faster than decoding the picture itself.
+#[derive(Debug, Default)]
+pub struct Previews {
+    /// Every preview, largest first.
+    pub jpegs: Vec<Preview>,
+    /// The EXIF orientation of the picture, which applies to its previews.
+    pub orientation: Option<u16>,
+}
+
+/// Largest APP1 segment of a JPEG, along with the markers before it.
+const JPEG_HEADER_SIZE: u64 = 128 * 1024;
+
+/// Panasonic RW2 files keep their preview in this IFD0 tag.
+const TAG_JPEG_FROM_RAW: u16 = 0x002e;
+const TAG_COMPRESSION: u16 = 0x0103;
+const TAG_STRIP_OFFSETS: u16 = 0x0111;
+const TAG_ORIENTATION: u16 = 0x0112;
+const TAG_STRIP_BYTE_COUNTS: u16 = 0x0117;
+const TAG_SUB_IFDS: u16 = 0x014a;
+const TAG_JPEG_OFFSET: u16 = 0x0201;
+const TAG_JPEG_LENGTH: u16 = 0x0202;
+
+/// Find the previews embedded in a JPEG, TIFF based RAW or Fujifilm RAF
+/// file. Only the metadata and the headers of the previews are read, RAW
+/// files can be large. Files without any give empty previews.
+pub fn read(path: &Path) -> Previews {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(_) => return Previews::default(),
+    };
+    let mut magic = [0; 4];
+    if file.read_exact(&mut magic).is_err() {
+        return Previews::default();
+    }
+
+    let (mut ranges, orientation) = match magic {
+        // The EXIF data of a JPEG is at its beginning.
+        [0xff, 0xd8, ..] => exif_offset(&mut file, 0)
+            .and_then(|tiff| Tiff::new(&mut file, tiff))
+            .map(|mut tiff| tiff.previews())
+            .unwrap_or_default(),
+        [b'F', b'U', b'J', b'I'] => {
+            let (offset, length) = match raf_jpeg_range(&mut file) {
+                Some(range) => range,
+                None => return Previews::default(),
+            };
+            // The preview carries the EXIF data of the picture, and a
+            // smaller preview of its own.
+            let (mut ranges, orientation) = exif_offset(&mut file, offset)
+                .and_then(|tiff| Tiff::new(&mut file, tiff))
+                .map(|mut tiff| tiff.previews())
+                .unwrap_or_default();
+            ranges.push((offset, length));
+            (ranges, orientation)
+        }
+        [b'I', b'I', ..] | [b'M', b'M', ..] => Tiff::new(&mut file, 0)
+            .map(|mut tiff| tiff.previews())
+            .unwrap_or_default(),
+        _ => return Previews::default(),
+    };
+
+    ranges.sort();
+    ranges.dedup();
+    let mut jpegs: Vec<Preview> = ranges
+        .into_iter()
+        .filter_map(|(offset, length)| {
+            let (width, height) = jpeg_dimensions(&mut file, offset, length)?;
+            Some(Preview {
+                offset,
+                length,
+                width,
+                height,
+            })
+        })
+        .collect();
+    jpegs.sort_by_key(|preview| Reverse(preview.width as u64 * preview.height as u64));
+    Previews { jpegs, orientation }
+}
+
+/// Read the JPEG data of a preview found by read().
+pub fn load(path: &Path, preview: &Preview) -> Option<Vec<u8>> {
+    let mut file = File::open(path).ok()?;
+    read_jpeg(&mut file, preview.offset, preview.length)
+}
+
+/// Offset of the JPEG preview offset and length in the header of RAF files.
+const RAF_JPEG_POINTER: u64 = 84;
+
+/// Read the JPEG preview of a Fujifilm RAF file, which also holds its EXIF
+/// data.
+pub fn read_raf_jpeg(file: &mut File) -> Option<Vec<u8>> {
+    let (offset, length) = raf_jpeg_range(file)?;
+    read_jpeg(file, offset, length)
+}
+
+/// Location of the JPEG preview of a Fujifilm RAF file.
+fn raf_jpeg_range(file: &mut File) -> Option<(u64, u64)> {
+    let mut pointer = [0; 8];
+    file.seek(SeekFrom::Start(RAF_JPEG_POINTER)).ok()?;
+    file.read_exact(&mut pointer).ok()?;
+    let offset = u32::from_be_bytes(pointer[..4].try_into().ok()?);
+    let length = u32::from_be_bytes(pointer[4..].try_into().ok()?);
+    Some((offset as u64, length as u64))
+}
+
+/// Read `length` bytes of JPEG data at `offset`.
+fn read_jpeg(file: &mut File, offset: u64, length: u64) -> Option<Vec<u8>> {
+    let mut jpeg = Vec::new();
+    file.seek(SeekFrom::Start(offset)).ok()?;
+    file.take(length).read_to_end(&mut jpeg).ok()?;
+    match jpeg.starts_with(&[0xff, 0xd8]) {
+        true => Some(jpeg),
+        false => None,
+    }
+}
+
+/// Offset in the file of the TIFF structure inside the APP1 segment of the
+/// JPEG starting at `start`.
+fn exif_offset(file: &mut File, start: u64) -> Option<u64> {
+    let mut jpeg = Vec::new();
+    file.seek(SeekFrom::Start(start)).ok()?;
+    file.take(JPEG_HEADER_SIZE).read_to_end(&mut jpeg).ok()?;
+    let mut offset = 2;
+    while offset + 4 <= jpeg.len() && jpeg[offset] == 0xff {
+        let marker = jpeg[offset + 1];
+        let length = u16::from_be_bytes([jpeg[offset + 2], jpeg[offset + 3]]) as usize;
+        let segment = jpeg.get(offset + 4..offset + 2 + length)?;
+        if marker == 0xe1 && segment.starts_with(b"Exif\0\0") {
+            return Some(start + offset as u64 + 10);
+        }
+        // The image data starts after SOS, there is no EXIF past it.
+        if marker == 0xda {
+            return None;
+        }
+        offset += 2 + length;
+    }
+    None
+}
+
+/// Width and height of the JPEG at `offset`, read from its frame header
+/// without decoding it.
+fn jpeg_dimensions(file: &mut File, offset: u64, length: u64) -> Option<(u32, u32)> {
+    let end = offset.checked_add(length)?;
+    let mut soi = [0; 2];
+    file.seek(SeekFrom::Start(offset)).ok()?;
+    file.read_exact(&mut soi).ok()?;
+    if soi != [0xff, 0xd8] {
+        return None;
+    }
+
+    let mut position = offset + 2;
+    while position + 4 <= end {
+        let mut marker = [0; 4];
+        file.seek(SeekFrom::Start(position)).ok()?;
+        file.read_exact(&mut marker).ok()?;
+        match marker {
+            // Markers may be padded with any number of 0xff.
+            [0xff, 0xff, ..] => position += 1,
+            // Start of frame, sharing its range with DHT, JPG and DAC.
+            [0xff, 0xc0..=0xcf, ..] if !matches!(marker[1], 0xc4 | 0xc8 | 0xcc) => {
+                let mut frame = [0; 5];
+                file.read_exact(&mut frame).ok()?;
+                let height = u16::from_be_bytes([frame[1], frame[2]]) as u32;
+                let width = u16::from_be_bytes([frame[3], frame[4]]) as u32;
+                return match width > 0 && height > 0 {
+                    true => Some((width, height)),
+                    false => None,
+                };
+            }
+            // The image data starts after SOS, the frame comes before it.
+            [0xff, 0xda, ..] => return None,
+            [0xff, _, high, low] => position += 2 + u16::from_be_bytes([high, low]) as u64,
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// A TIFF structure, read from the file as needed.
+struct Tiff<'a> {
+    file: &'a mut File,
+    /// Offset of the structure in the file, which its offsets start from.
+    base: u64,
+    big_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(file: &'a mut File, base: u64) -> Option<Self> {
+        let mut order = [0; 2];
+        file.seek(SeekFrom::Start(base)).ok()?;
+        file.read_exact(&mut order).ok()?;
+        let big_endian = match &order {
+            b"II" => false,
+            b"MM" => true,
+            _ => return None,
+        };
+        Some(Tiff {
+            file,
+            base,
+            big_endian,
+        })
+    }
+
+    /// Read `length` bytes at `offset` in the structure.
+    fn read(&mut self, offset: u64, length: usize) -> Option<Vec<u8>> {
+        let mut data = vec![0; length];
+        self.file
+            .seek(SeekFrom::Start(self.base.checked_add(offset)?))
+            .ok()?;
+        self.file.read_exact(&mut data).ok()?;
+        Some(data)
+    }
+
+    fn u16(&self, bytes: &[u8], offset: usize) -> Option<u16> {
+        let bytes = bytes.get(offset..offset + 2)?.try_into().ok()?;
+        Some(match self.big_endian {
+            true => u16::from_be_bytes(bytes),
+            false => u16::from_le_bytes(bytes),
+        })
+    }
+    fn u32(&self, bytes: &[u8], offset: usize) -> Option<u32> {
+        let bytes = bytes.get(offset..offset + 4)?.try_into().ok()?;
+        Some(match self.big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        })
+    }
+
+    /// Values of a SHORT, LONG or IFD entry, stored in the entry itself when
+    /// they fit, or elsewhere in the file.
+    fn values(&mut self, entry: &[u8]) -> Vec<u32> {
+        let (kind, count) = match (self.u16(entry, 2), self.u32(entry, 4)) {
+            (Some(kind), Some(count)) => (kind, count as usize),
+            _ => return Vec::new(),
+        };
+        let size = match kind {
+            3 => 2,
+            4 | 13 => 4,
+            _ => return Vec::new(),
+        };
+        let count_read = count.min(1024);
+        let data = match size * count <= 4 {
+            true => entry.get(8..12).map(|data| data.to_vec()),
+            false => self
+                .u32(entry, 8)
+                .and_then(|offset| self.read(offset as u64, size * count_read)),
+        };
+        let data = match data {
+            Some(data) => data,
+            None => return Vec::new(),
+        };
+        (0..count_read)
+            .map_while(|index| match size {
+                2 => self.u16(&data, 2 * index).map(u32::from),
+                _ => self.u32(&data, 4 * index),
+            })
+            .collect()
+    }
+
+    /// Walk every IFD, following the chain from IFD0 and the SubIFDs where
+    /// RAW files keep their previews. Returns the location in the file of
+    /// every JPEG found, and the orientation of the picture.
+    fn previews(&mut self) -> (Vec<(u64, u64)>, Option<u16>) {
+        let mut orientation = None;
+        let mut ranges = Vec::new();
+        let mut pending: Vec<u64> = self
+            .read(4, 4)
+            .and_then(|first| self.u32(&first, 0))
+            .map(u64::from)
+            .into_iter()
+            .collect();
+        let mut visited = HashSet::new();
+        while let Some(ifd) = pending.pop() {
+            // Broken files may have loops.
+            if ifd == 0 || !visited.insert(ifd) || visited.len() > 64 {
+                continue;
+            }
+            let count = match self.read(ifd, 2).and_then(|count| self.u16(&count, 0)) {
+                Some(count) => count as usize,
+                None => continue,
+            };
+            let entries = match self.read(ifd + 2, 12 * count) {
+                Some(entries) => entries,
+                None => continue,
+            };
+
+            let mut compression = None;
+            let (mut jpeg_offset, mut jpeg_length) = (None, None);
+            let (mut strip_offsets, mut strip_lengths) = (Vec::new(), Vec::new());
+            for entry in entries.chunks_exact(12) {
+                match self.u16(entry, 0) {
+                    Some(TAG_COMPRESSION) => compression = self.values(entry).first().copied(),
+                    Some(TAG_STRIP_OFFSETS) => strip_offsets = self.values(entry),
+                    Some(TAG_STRIP_BYTE_COUNTS) => strip_lengths = self.values(entry),
+                    Some(TAG_JPEG_OFFSET) => jpeg_offset = self.values(entry).first().copied(),
+                    Some(TAG_JPEG_LENGTH) => jpeg_length = self.values(entry).first().copied(),
+                    Some(TAG_JPEG_FROM_RAW) => {
+                        if let (Some(length), Some(offset)) =
+                            (self.u32(entry, 4), self.u32(entry, 8))
+                        {
+                            ranges.push((offset, length));
+                        }
+                    }
+                    Some(TAG_SUB_IFDS) => {
+                        pending.extend(self.values(entry).iter().map(|&sub| sub as u64))
+                    }
+                    // Only IFD0 describes the picture itself.
+                    Some(TAG_ORIENTATION) if visited.len() == 1 => {
+                        orientation = self.values(entry).first().map(|&value| value as u16)
+                    }
+                    _ => (),
+                }
+            }
+
+            if let (Some(offset), Some(length)) = (jpeg_offset, jpeg_length) {
+                ranges.push((offset, length));
+            }
+            // Old and new style JPEG compression, in a single strip.
+            if matches!(compression, Some(6) | Some(7))
+                && strip_offsets.len() == 1
+                && strip_lengths.len() == 1
+            {
+                ranges.push((strip_offsets[0], strip_lengths[0]));
+            }
+            let next = self.read(ifd + 2 + 12 * count as u64, 4);
+            if let Some(next) = next.and_then(|next| self.u32(&next, 0)) {
+                pending.push(next as u64);
+            }
+        }
+
+        let ranges = ranges
+            .into_iter()
+            .map(|(offset, length)| (self.base + offset as u64, length as u64))
+            .collect();
+        (ranges, orientation)
+    }
+}
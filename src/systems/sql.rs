@@ -1,5 +1,11 @@
-pub fn sql_schema() -> String {
-    let string = "
+/// Schema migrations, in order. The `user_version` pragma of a database is the
+/// number of migrations applied to it. Released migrations must never be
+/// edited, as existing libraries would not pick up the change; add a new one
+/// at the end instead.
+pub const MIGRATIONS: &[&str] = &[
+    // 1: Initial schema. Databases created before migrations existed are at
+    // version 0 but already have this table, hence IF NOT EXISTS.
+    "
         -- Table where each row represents a picture.
         CREATE TABLE IF NOT EXISTS pictures (
             id                      INTEGER NOT NULL UNIQUE,
@@ -7,7 +13,157 @@ pub fn sql_schema() -> String {
             thumbnail               TEXT,
             PRIMARY KEY(id AUTOINCREMENT)
         );
-    ";
+    ",
+    // 2: Albums, tags, ratings, capture metadata and videos.
+    "
+        ALTER TABLE pictures ADD COLUMN mime TEXT;
+        ALTER TABLE pictures ADD COLUMN rating INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE pictures ADD COLUMN kind INTEGER NOT NULL DEFAULT 0;
 
-    string.to_string()
-}
+        -- Table where each row represents an album. The cover is optional, and
+        -- is reset when the picture is removed from the album.
+        CREATE TABLE albums (
+            id                      INTEGER NOT NULL UNIQUE,
+            name                    TEXT NOT NULL,
+            cover                   INTEGER REFERENCES pictures(id) ON DELETE SET NULL,
+            PRIMARY KEY(id AUTOINCREMENT)
+        );
+
+        -- Membership of pictures in albums. Position is zero-based and kept
+        -- contiguous by the database subsystem.
+        CREATE TABLE album_pictures (
+            album                   INTEGER NOT NULL REFERENCES albums(id) ON DELETE CASCADE,
+            picture                 INTEGER NOT NULL REFERENCES pictures(id) ON DELETE CASCADE,
+            position                INTEGER NOT NULL,
+            PRIMARY KEY(album, picture)
+        );
+        CREATE INDEX album_pictures_position ON album_pictures(album, position);
+
+        -- Table where each row represents a keyword. Keywords form a tree, so
+        -- that Places/Italy/Rome is stored as three rows.
+        CREATE TABLE tags (
+            id                      INTEGER NOT NULL UNIQUE,
+            name                    TEXT NOT NULL,
+            parent                  INTEGER REFERENCES tags(id) ON DELETE CASCADE,
+            PRIMARY KEY(id AUTOINCREMENT)
+        );
+        CREATE INDEX tags_parent ON tags(parent, name);
+
+        -- Assignment of tags to pictures.
+        CREATE TABLE picture_tags (
+            picture                 INTEGER NOT NULL REFERENCES pictures(id) ON DELETE CASCADE,
+            tag                     INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+            PRIMARY KEY(picture, tag)
+        );
+        CREATE INDEX picture_tags_tag ON picture_tags(tag);
+
+        -- Capture metadata, mostly read from EXIF. One row per picture.
+        CREATE TABLE picture_metadata (
+            picture                 INTEGER NOT NULL UNIQUE REFERENCES pictures(id) ON DELETE CASCADE,
+            date_taken              TEXT,
+            camera_make             TEXT,
+            camera_model            TEXT,
+            lens                    TEXT,
+            exposure_time           REAL,
+            f_number                REAL,
+            iso                     INTEGER,
+            focal_length            REAL,
+            orientation             INTEGER,
+            latitude                REAL,
+            longitude               REAL,
+            width                   INTEGER,
+            height                  INTEGER,
+            PRIMARY KEY(picture)
+        );
+        CREATE INDEX picture_metadata_date_taken ON picture_metadata(date_taken);
+
+        -- Container metadata of videos. The creation time and dimensions are
+        -- also written to picture_metadata, so that videos sort with pictures.
+        CREATE TABLE video_metadata (
+            picture                 INTEGER NOT NULL UNIQUE REFERENCES pictures(id) ON DELETE CASCADE,
+            duration                REAL,
+            width                   INTEGER,
+            height                  INTEGER,
+            creation_time           TEXT,
+            codec                   TEXT,
+            PRIMARY KEY(picture)
+        );
+    ",
+    // 3: Content hashes, for duplicate detection.
+    "
+        ALTER TABLE pictures ADD COLUMN hash TEXT;
+        CREATE INDEX pictures_hash ON pictures(hash);
+    ",
+    // 4: Pictures whose file could not be found are kept, but marked.
+    "
+        ALTER TABLE pictures ADD COLUMN missing INTEGER NOT NULL DEFAULT 0;
+    ",
+    // 5: Thumbnails of every size. pictures.thumbnail keeps the default one.
+    "
+        CREATE TABLE thumbnails (
+            picture                 INTEGER NOT NULL REFERENCES pictures(id) ON DELETE CASCADE,
+            size                    INTEGER NOT NULL,
+            path                    TEXT NOT NULL,
+            PRIMARY KEY(picture, size)
+        );
+    ",
+    // 6: Camera RAW files.
+    "
+        ALTER TABLE pictures ADD COLUMN raw INTEGER NOT NULL DEFAULT 0;
+    ",
+    // 7: Stacks of related pictures. When the top picture is removed from
+    // the library, the first remaining picture takes its place.
+    "
+        CREATE TABLE stacks (
+            id                      INTEGER PRIMARY KEY AUTOINCREMENT,
+            kind                    INTEGER NOT NULL DEFAULT 0,
+            top                     INTEGER REFERENCES pictures(id) ON DELETE SET NULL
+        );
+
+        CREATE TABLE stack_pictures (
+            stack                   INTEGER NOT NULL REFERENCES stacks(id) ON DELETE CASCADE,
+            picture                 INTEGER NOT NULL UNIQUE REFERENCES pictures(id) ON DELETE CASCADE,
+            PRIMARY KEY(stack, picture)
+        );
+    ",
+    // 8: Live Photos and motion photos. Apple pairs a still with a video
+    // file through a content identifier, Android appends the video to the
+    // JPEG file itself.
+    "
+        ALTER TABLE pictures ADD COLUMN content_id TEXT;
+        CREATE INDEX pictures_content_id ON pictures(content_id);
+
+        CREATE TABLE live_photos (
+            picture                 INTEGER NOT NULL UNIQUE REFERENCES pictures(id) ON DELETE CASCADE,
+            video                   INTEGER UNIQUE REFERENCES pictures(id) ON DELETE CASCADE,
+            video_offset            INTEGER,
+            video_length            INTEGER,
+            PRIMARY KEY(picture)
+        );
+    ",
+    // 9: Size and modification time of each file, to find the files that
+    // changed without reading them. Filenames are looked up on every scan.
+    "
+        ALTER TABLE pictures ADD COLUMN size INTEGER;
+        ALTER TABLE pictures ADD COLUMN mtime INTEGER;
+        CREATE INDEX pictures_filename ON pictures(filename);
+    ",
+    // 10: Thumbnails of the same size can be cropped differently.
+    "
+        CREATE TABLE thumbnails_cropped (
+            picture                 INTEGER NOT NULL REFERENCES pictures(id) ON DELETE CASCADE,
+            size                    INTEGER NOT NULL,
+            crop                    INTEGER NOT NULL DEFAULT 0,
+            path                    TEXT NOT NULL,
+            PRIMARY KEY(picture, size, crop)
+        );
+        INSERT INTO thumbnails_cropped (picture, size, path) SELECT picture, size, path FROM thumbnails;
+        DROP TABLE thumbnails;
+        ALTER TABLE thumbnails_cropped RENAME TO thumbnails;
+    ",
+    // 11: Pictures of folders removed from the library with their metadata
+    // kept. They are marked as missing too, so that they are hidden.
+    "
+        ALTER TABLE pictures ADD COLUMN detached INTEGER NOT NULL DEFAULT 0;
+    ",
+];
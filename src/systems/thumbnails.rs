@@ -0,0 +1,167 @@
+use crate::error::LumenzaError;
+use crate::picture::Picture;
+use crate::systems::hash;
+use crate::thumbnail::{ThumbnailCrop, ThumbnailProfile, Thumbnailer};
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Size, crop and path of a generated thumbnail.
+pub type Thumbnail = (u32, ThumbnailCrop, PathBuf);
+
+/// Location of the thumbnail of a picture in the cache, keyed by the hash of
+/// its content. Thumbnails are spread over subfolders named after the first
+/// two characters of the hash, to keep folders small. Identical pictures
+/// share the same thumbnails.
+pub fn path(folder: &Path, hash: &str, profile: &ThumbnailProfile) -> PathBuf {
+    let name = format!(
+        "{}-{}{}.{}",
+        hash,
+        profile.size,
+        profile.suffix(),
+        profile.extension()
+    );
+    folder.join(&hash[..2]).join(name)
+}
+
+/// Whether the thumbnail is missing, or older than its source file.
+pub fn is_outdated(thumbnail: &Path, source: &Path) -> bool {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    match (modified(thumbnail), modified(source)) {
+        (Some(thumbnail), Some(source)) => thumbnail < source,
+        _ => true,
+    }
+}
+
+/// Whether the picture already has up to date thumbnails in the cache, for
+/// every profile.
+pub fn is_current(picture: &Picture, folder: &Path, profiles: &[ThumbnailProfile]) -> bool {
+    let hash = match &picture.hash {
+        Some(hash) => hash,
+        None => return false,
+    };
+    profiles.len() == picture.thumbnails.len()
+        && profiles.iter().all(|profile| {
+            let thumbnail = path(folder, hash, profile);
+            picture
+                .thumbnails
+                .contains(&(profile.size, profile.crop, thumbnail.clone()))
+                && !is_outdated(&thumbnail, &picture.filename)
+        })
+}
+
+/// Generate the thumbnails of a picture in the cache, unless they are there
+/// already. Returns the content hash of the picture and the thumbnail of
+/// each profile, which the caller saves to the database. Doesn't touch the
+/// database, so it can run on any thread.
+pub fn update(
+    picture: &Picture,
+    folder: &Path,
+    profiles: &[ThumbnailProfile],
+    thumbnailer: &dyn Thumbnailer,
+) -> Result<(String, Vec<Thumbnail>), LumenzaError> {
+    // A thumbnail older than its picture means the file was edited, so its
+    // content hash has to be computed again.
+    let edited = picture.thumbnail.is_file() && is_outdated(&picture.thumbnail, &picture.filename);
+    let hash = match &picture.hash {
+        Some(hash) if !edited => hash.clone(),
+        _ => hash::hash_file(&picture.filename)?,
+    };
+
+    let mut thumbnails = Vec::new();
+    let mut outdated = Vec::new();
+    for profile in profiles {
+        let thumbnail = path(folder, &hash, profile);
+        if is_outdated(&thumbnail, &picture.filename) {
+            let shard = thumbnail.parent().unwrap();
+            std::fs::create_dir_all(shard).map_err(|_| LumenzaError::IoError())?;
+            // Identical pictures share thumbnails, and may be thumbnailed at
+            // the same time, so write to a file of our own then move it in
+            // place.
+            let partial = thumbnail.with_extension(format!("{}.tmp", picture.id));
+            outdated.push((partial, *profile));
+        }
+        thumbnails.push((profile.size, profile.crop, thumbnail));
+    }
+    // The picture is opened once for all of its thumbnails.
+    if !outdated.is_empty() {
+        thumbnailer.generate_all(picture, &outdated)?;
+    }
+    for (partial, profile) in &outdated {
+        let thumbnail = path(folder, &hash, profile);
+        std::fs::rename(partial, &thumbnail).map_err(|_| LumenzaError::IoError())?;
+    }
+    Ok((hash, thumbnails))
+}
+
+/// Delete the files of the cache that are not in `keep`. Returns the number
+/// of files deleted. The thumbnail folder may be shared with other files,
+/// like the config or the database, so only files named the way path() and
+/// update() name them are deleted.
+pub fn remove_orphans(folder: &Path, keep: &HashSet<PathBuf>) -> Result<usize, LumenzaError> {
+    let mut removed = 0;
+    for entry in WalkDir::new(folder).min_depth(2).max_depth(2) {
+        let entry = entry.map_err(|_| LumenzaError::IoError())?;
+        if entry.file_type().is_file()
+            && !keep.contains(entry.path())
+            && is_cache_file(folder, entry.path())
+        {
+            std::fs::remove_file(entry.path()).map_err(|_| LumenzaError::IoError())?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Extensions of the thumbnails written to the cache, one per format.
+const EXTENSIONS: &[&str] = &["jpg", "png", "webp"];
+
+/// Whether the file is a thumbnail of the cache, or one being written:
+/// `<shard>/<hash>-<size><suffix>.<extension>`, where the shard is the start
+/// of the hash, and files being written end with `.<picture id>.tmp`.
+fn is_cache_file(folder: &Path, file: &Path) -> bool {
+    let relative = match file.strip_prefix(folder) {
+        Ok(relative) => relative,
+        Err(_) => return false,
+    };
+    let mut components = relative.iter().map(|component| component.to_str());
+    let (shard, name) = match (components.next(), components.next(), components.next()) {
+        (Some(Some(shard)), Some(Some(name)), None) => (shard, name),
+        _ => return false,
+    };
+    let is_hex = |text: &str| {
+        !text.is_empty()
+            && text
+                .bytes()
+                .all(|byte| byte.is_ascii_digit() || (b'a'..=b'f').contains(&byte))
+    };
+    let is_number = |text: &str| !text.is_empty() && text.bytes().all(|byte| byte.is_ascii_digit());
+
+    let stem = match name.strip_suffix(".tmp") {
+        Some(partial) => match partial.rsplit_once('.') {
+            Some((stem, id)) if is_number(id) => stem,
+            _ => return false,
+        },
+        None => match name.rsplit_once('.') {
+            Some((stem, extension)) if EXTENSIONS.contains(&extension) => stem,
+            _ => return false,
+        },
+    };
+    let (hash, profile) = match stem.split_once('-') {
+        Some((hash, profile)) => (hash, Some(profile)),
+        None => (stem, None),
+    };
+    let profile_matches = match profile {
+        Some(profile) => {
+            let size = profile.trim_end_matches(|c: char| c.is_ascii_lowercase());
+            is_number(size)
+        }
+        None => true,
+    };
+    shard.len() == 2
+        && hash.len() == hash::HASH_LENGTH
+        && is_hex(hash)
+        && hash.starts_with(shard)
+        && profile_matches
+}
@@ -0,0 +1,50 @@
+/// Hierarchical keywords
+///
+/// Tags are organized as a tree, and are written as paths such as
+/// `Places/Italy/Rome`. Tagging a picture with `Rome` implicitly places it
+/// under `Italy` and `Places` when listing pictures including descendants.
+/// Tags are created, assigned and listed through the Library.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tag {
+    pub(crate) id: u32,
+    pub(crate) name: String,
+    pub(crate) path: String,
+    pub(crate) parent: Option<u32>,
+}
+
+/// Separator between the levels of a tag path.
+pub(crate) const TAG_SEPARATOR: char = '/';
+
+// Instance methods
+impl Tag {
+    /// Get the database id of the tag.
+    pub fn get_id(&self) -> u32 {
+        self.id
+    }
+    /// Get the name of the tag, without its parents (`Rome`).
+    pub fn get_name(&self) -> String {
+        self.name.clone()
+    }
+    /// Get the full path of the tag (`Places/Italy/Rome`).
+    pub fn get_path(&self) -> String {
+        self.path.clone()
+    }
+    /// Get the id of the parent tag. Top level tags do not have a parent.
+    pub fn get_parent(&self) -> Option<u32> {
+        self.parent
+    }
+}
+
+/// Split a tag path into its names, making sure none of them are empty.
+pub(crate) fn split_path(path: &str) -> Option<Vec<&str>> {
+    let names: Vec<&str> = path.split(TAG_SEPARATOR).map(|name| name.trim()).collect();
+    if names.iter().any(|name| name.is_empty()) {
+        return None;
+    }
+    Some(names)
+}
+
+/// Check that a name can be used for a single level of the tag tree.
+pub(crate) fn is_valid_name(name: &str) -> bool {
+    !name.trim().is_empty() && !name.contains(TAG_SEPARATOR)
+}
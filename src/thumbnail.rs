@@ -0,0 +1,402 @@
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::metadata::Orientation;
+use image::{DynamicImage, GenericImageView, ImageDecoder, ImageReader};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::error::LumenzaError;
+use crate::picture::Picture;
+use crate::systems::preview;
+
+/// Image format of generated thumbnails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThumbnailFormat {
+    /// Smallest files, but transparency is lost.
+    Jpeg,
+    /// Lossless, so the quality is ignored.
+    Png,
+    /// Lossless, so the quality is ignored: the image crate has no lossy
+    /// WebP encoder. Files are smaller than PNG, larger than JPEG.
+    WebP,
+}
+
+/// How thumbnails are fitted to their size.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThumbnailCrop {
+    /// The whole picture is kept, fitting in a square of the profile's size.
+    #[default]
+    Fit,
+    /// A square tile, taken from the middle of the picture.
+    Center,
+    /// A square tile, taken from the most detailed part of the picture.
+    /// Useful for grids, where a centered crop often cuts off the subject.
+    Smart,
+}
+
+// Static methods
+impl ThumbnailCrop {
+    pub(crate) fn from_i64(crop: i64) -> Self {
+        match crop {
+            1 => ThumbnailCrop::Center,
+            2 => ThumbnailCrop::Smart,
+            _ => ThumbnailCrop::Fit,
+        }
+    }
+}
+
+// Instance methods
+impl ThumbnailCrop {
+    pub(crate) fn to_i64(self) -> i64 {
+        match self {
+            ThumbnailCrop::Fit => 0,
+            ThumbnailCrop::Center => 1,
+            ThumbnailCrop::Smart => 2,
+        }
+    }
+}
+
+/// A size of thumbnail generated for every picture
+///
+/// A library can have several, for instance a small one for a grid of
+/// pictures and a large one for previews. They are stored in the config, and
+/// set with Library::set_thumbnail_profiles().
+///
+/// ```ignore
+/// let grid = ThumbnailProfile::new(128, ThumbnailFormat::Jpeg, 80).crop(ThumbnailCrop::Smart);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ThumbnailProfile {
+    pub(crate) size: u32,
+    pub(crate) format: ThumbnailFormat,
+    pub(crate) quality: u8,
+    #[serde(default)]
+    pub(crate) crop: ThumbnailCrop,
+}
+
+// Static methods
+impl ThumbnailProfile {
+    /// Thumbnails fitting in a square of `size` pixels, in the given format.
+    /// The quality goes from 1 to 100, and is only used by JPEG; PNG and
+    /// WebP thumbnails are always lossless.
+    /// Pictures are never enlarged, and are turned upright according to
+    /// their EXIF orientation.
+    pub fn new(size: u32, format: ThumbnailFormat, quality: u8) -> Self {
+        ThumbnailProfile {
+            size,
+            format,
+            quality,
+            crop: ThumbnailCrop::Fit,
+        }
+    }
+}
+
+// Instance methods
+impl ThumbnailProfile {
+    /// Crop the thumbnails to a square.
+    pub fn crop(mut self, crop: ThumbnailCrop) -> Self {
+        self.crop = crop;
+        self
+    }
+
+    /// Get the largest side of the thumbnails, in pixels.
+    pub fn get_size(&self) -> u32 {
+        self.size
+    }
+    /// Get the image format of the thumbnails.
+    pub fn get_format(&self) -> ThumbnailFormat {
+        self.format
+    }
+    /// Get the quality of the thumbnails, from 1 to 100. Only JPEG
+    /// thumbnails use it.
+    pub fn get_quality(&self) -> u8 {
+        self.quality
+    }
+    /// Get how the thumbnails are cropped.
+    pub fn get_crop(&self) -> ThumbnailCrop {
+        self.crop
+    }
+
+    pub(crate) fn is_valid(&self) -> bool {
+        self.size > 0 && (1..=100).contains(&self.quality)
+    }
+    /// Suffix of the thumbnail file names, telling profiles of the same
+    /// size but different crops apart.
+    pub(crate) fn suffix(&self) -> &'static str {
+        match self.crop {
+            ThumbnailCrop::Fit => "",
+            ThumbnailCrop::Center => "c",
+            ThumbnailCrop::Smart => "s",
+        }
+    }
+    pub(crate) fn extension(&self) -> &'static str {
+        match self.format {
+            ThumbnailFormat::Jpeg => "jpg",
+            ThumbnailFormat::Png => "png",
+            ThumbnailFormat::WebP => "webp",
+        }
+    }
+}
+
+impl Default for ThumbnailProfile {
+    fn default() -> Self {
+        ThumbnailProfile::new(256, ThumbnailFormat::Jpeg, 85)
+    }
+}
+
+/// Generator of thumbnails
+///
+/// Lumenza uses ImageThumbnailer by default. Clients can register their own
+/// with Library::set_thumbnailer(), for instance to use the thumbnailing
+/// library of the target platform, which may be faster or support more
+/// formats, while Lumenza still takes care of caching, running on every core
+/// and saving the thumbnails to the database. It is called from several
+/// threads at once.
+pub trait Thumbnailer: Send + Sync {
+    /// Write a thumbnail of the picture to `thumbnail`, following the size,
+    /// format and crop of the profile. The file name of `thumbnail` is not
+    /// meaningful, the file must be written in the profile's format.
+    fn generate(
+        &self,
+        picture: &Picture,
+        thumbnail: &Path,
+        profile: &ThumbnailProfile,
+    ) -> Result<(), LumenzaError>;
+
+    /// Write the thumbnails of several profiles of the same picture, each to
+    /// its own path. Calls generate() for each of them by default;
+    /// thumbnailers can do better by opening the picture only once.
+    fn generate_all(
+        &self,
+        picture: &Picture,
+        thumbnails: &[(PathBuf, ThumbnailProfile)],
+    ) -> Result<(), LumenzaError> {
+        for (thumbnail, profile) in thumbnails {
+            self.generate(picture, thumbnail, profile)?;
+        }
+        Ok(())
+    }
+}
+
+/// Default thumbnail generation using the image crate.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ImageThumbnailer;
+
+impl Thumbnailer for ImageThumbnailer {
+    fn generate(
+        &self,
+        picture: &Picture,
+        thumbnail: &Path,
+        profile: &ThumbnailProfile,
+    ) -> Result<(), LumenzaError> {
+        self.generate_all(picture, &[(thumbnail.to_path_buf(), *profile)])
+    }
+
+    fn generate_all(
+        &self,
+        picture: &Picture,
+        thumbnails: &[(PathBuf, ThumbnailProfile)],
+    ) -> Result<(), LumenzaError> {
+        // Profiles of similar sizes often use the same source, which is
+        // only decoded once.
+        let source = Source::read(picture);
+        let mut opened: Vec<(Option<preview::Preview>, DynamicImage)> = Vec::new();
+        for (thumbnail, profile) in thumbnails {
+            let chosen = source.choose(profile);
+            let index = match opened.iter().position(|(other, _)| *other == chosen) {
+                Some(index) => index,
+                None => {
+                    opened.push((chosen, source.open(chosen.as_ref())?));
+                    opened.len() - 1
+                }
+            };
+            write_thumbnail(&opened[index].1, thumbnail, profile)?;
+        }
+        Ok(())
+    }
+}
+
+/// A picture to thumbnail, along with the previews embedded in it.
+struct Source<'a> {
+    picture: &'a Picture,
+    previews: preview::Previews,
+    /// Aspect ratio of the picture, which previews must have.
+    ratio: Option<f64>,
+}
+
+impl<'a> Source<'a> {
+    fn read(picture: &'a Picture) -> Self {
+        Source {
+            picture,
+            previews: preview::read(&picture.filename),
+            ratio: image::image_dimensions(&picture.filename)
+                .ok()
+                .map(|(width, height)| width as f64 / height as f64),
+        }
+    }
+
+    /// The smallest preview large enough for the profile. RAW files can't be
+    /// decoded, so they fall back to their largest preview.
+    fn choose(&self, profile: &ThumbnailProfile) -> Option<preview::Preview> {
+        // Previews are sometimes letterboxed to another aspect ratio, which
+        // would show as borders.
+        let mut candidates = self.previews.jpegs.iter().filter(|preview| {
+            let ratio = preview.width as f64 / preview.height as f64;
+            self.ratio
+                .is_none_or(|expected| (ratio - expected).abs() <= 0.02)
+        });
+        let large_enough = |preview: &&preview::Preview| {
+            let (width, height) = (preview.width, preview.height);
+            let side = match profile.crop {
+                ThumbnailCrop::Fit => width.max(height),
+                ThumbnailCrop::Center | ThumbnailCrop::Smart => width.min(height),
+            };
+            side >= profile.size
+        };
+        match candidates.clone().rev().find(large_enough) {
+            Some(preview) => Some(*preview),
+            None if self.picture.raw => candidates.next().copied(),
+            None => None,
+        }
+    }
+
+    /// Open the preview, or the picture itself without one, the right way
+    /// up.
+    fn open(&self, preview: Option<&preview::Preview>) -> Result<DynamicImage, LumenzaError> {
+        let path = &self.picture.filename;
+        let decoded = preview
+            .and_then(|preview| preview::load(path, preview))
+            .and_then(|jpeg| {
+                image::load_from_memory_with_format(&jpeg, image::ImageFormat::Jpeg).ok()
+            });
+        match decoded {
+            Some(mut img) => {
+                let orientation = self
+                    .previews
+                    .orientation
+                    .and_then(|value| Orientation::from_exif(value as u8))
+                    .unwrap_or(Orientation::NoTransforms);
+                img.apply_orientation(orientation);
+                Ok(img)
+            }
+            None if self.picture.raw => Err(LumenzaError::ImageError()),
+            None => open_upright(path),
+        }
+    }
+}
+
+/// Crop and resize the picture for the profile, and write it to `thumbnail`.
+fn write_thumbnail(
+    img: &DynamicImage,
+    thumbnail: &Path,
+    profile: &ThumbnailProfile,
+) -> Result<(), LumenzaError> {
+    // Cut a square out of it, if requested
+    let (width, height) = img.dimensions();
+    let side = width.min(height);
+    let offset = match profile.crop {
+        ThumbnailCrop::Fit => None,
+        ThumbnailCrop::Center => Some((width.max(height) - side) / 2),
+        ThumbnailCrop::Smart => Some(smart_crop_offset(img)),
+    };
+    let cropped = offset.map(|offset| match width > height {
+        true => img.crop_imm(offset, 0, side, side),
+        false => img.crop_imm(0, offset, side, side),
+    });
+    let img = cropped.as_ref().unwrap_or(img);
+
+    // Fit it in the bounding box, maintaining the aspect ratio
+    let (width, height) = img.dimensions();
+    let resized = match width > profile.size || height > profile.size {
+        true => Some(img.resize(
+            profile.size,
+            profile.size,
+            image::imageops::FilterType::Lanczos3,
+        )),
+        false => None,
+    };
+    let resized_img = resized.as_ref().unwrap_or(img);
+
+    // Save the resized image to a new file
+    let file = std::fs::File::create(thumbnail).map_err(|_| LumenzaError::IoError())?;
+    let mut writer = std::io::BufWriter::new(file);
+    let result = match profile.format {
+        // JPEG has no transparency.
+        ThumbnailFormat::Jpeg => resized_img
+            .to_rgb8()
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut writer, profile.quality)),
+        ThumbnailFormat::Png => resized_img.write_to(&mut writer, image::ImageFormat::Png),
+        ThumbnailFormat::WebP => resized_img
+            .to_rgba8()
+            .write_with_encoder(WebPEncoder::new_lossless(&mut writer)),
+    };
+    result.map_err(|_| LumenzaError::ImageError())?;
+
+    Ok(())
+}
+
+/// Open an image, applying its EXIF orientation so that photos taken with a
+/// rotated camera are upright.
+fn open_upright(path: &Path) -> Result<DynamicImage, LumenzaError> {
+    let reader = ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|_| LumenzaError::FileNotFound())?;
+    let mut decoder = reader
+        .into_decoder()
+        .map_err(|_| LumenzaError::ImageError())?;
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let mut img = DynamicImage::from_decoder(decoder).map_err(|_| LumenzaError::ImageError())?;
+    img.apply_orientation(orientation);
+    Ok(img)
+}
+
+/// Side of the grayscale copy used to find the most detailed square.
+const SMART_CROP_SIDE: u32 = 32;
+
+/// Offset, along the longest side of the image, of the square holding the
+/// most detail. Detail is measured by the gradient of a small grayscale copy,
+/// which tends to follow the subject rather than a plain sky or wall.
+fn smart_crop_offset(img: &DynamicImage) -> u32 {
+    let (width, height) = img.dimensions();
+    let (side, long) = (width.min(height), width.max(height));
+    if side == long {
+        return 0;
+    }
+    let small_long = (long as u64 * SMART_CROP_SIDE as u64 / side as u64).max(1) as u32;
+    let (small_width, small_height) = match width > height {
+        true => (small_long, SMART_CROP_SIDE),
+        false => (SMART_CROP_SIDE, small_long),
+    };
+    let gray = img
+        .resize_exact(
+            small_width,
+            small_height,
+            image::imageops::FilterType::Triangle,
+        )
+        .to_luma8();
+
+    // Detail of each line across the longest side.
+    let mut detail = vec![0u64; small_long as usize];
+    for (x, y, pixel) in gray.enumerate_pixels() {
+        let value = pixel.0[0] as i64;
+        let left = gray.get_pixel(x.saturating_sub(1), y).0[0] as i64;
+        let above = gray.get_pixel(x, y.saturating_sub(1)).0[0] as i64;
+        let line = if width > height { x } else { y };
+        detail[line as usize] += ((value - left).abs() + (value - above).abs()) as u64;
+    }
+
+    // Slide a square along it, keeping the most detailed position.
+    let window = SMART_CROP_SIDE.min(small_long) as usize;
+    let mut sum: u64 = detail[..window].iter().sum();
+    let (mut best, mut best_sum) = (0, sum);
+    for start in 1..=detail.len() - window {
+        sum = sum + detail[start + window - 1] - detail[start - 1];
+        if sum > best_sum {
+            (best, best_sum) = (start, sum);
+        }
+    }
+    let offset = (best as u64 * long as u64 / small_long as u64) as u32;
+    offset.min(long - side)
+}
@@ -0,0 +1,20 @@
+use std::path::PathBuf;
+
+use crate::picture::Picture;
+
+/// A change applied to the library while watching its folders
+///
+/// Emitted by Library::watch() once the change is in the database, so the
+/// pictures carry their new state.
+#[derive(Debug, Clone)]
+pub enum WatchEvent {
+    /// A file was added to a folder, or came back after going missing.
+    Added(Picture),
+    /// A file was deleted. Its picture is marked as missing, keeping its
+    /// tags and albums, until purge_missing() is called.
+    Removed(Picture),
+    /// A file was moved or renamed within the library's folders.
+    Moved { from: PathBuf, picture: Picture },
+    /// The content of a file changed. Its hash and metadata were updated.
+    Modified(Picture),
+}
@@ -1,6 +1,11 @@
 #[cfg(test)]
 mod tests {
-    use lumenza::Library;
+    use lumenza::error::LumenzaError;
+    use lumenza::filter::{Filter, Order, Query, Sort};
+    use lumenza::{
+        CancelToken, Library, LibraryEvent, LiveVideo, MediaKind, Picture, StackKind,
+        ThumbnailCrop, ThumbnailFormat, ThumbnailProfile, Thumbnailer, WatchEvent,
+    };
     use std::path::{self, PathBuf};
     use tempdir::TempDir;
 
@@ -42,9 +47,19 @@ mod tests {
 
         let mut library_new = Library::create(&config, &thumbnails, &database).unwrap();
         let folder_path = path::Path::new("tests/images/").to_path_buf();
-        let res = library_new.process_folder(&folder_path).unwrap();
+        let report = library_new.process_folder(&folder_path).unwrap();
 
-        assert_eq!(res, ());
+        assert_eq!(2, report.get_added().len());
+        assert!(report.get_unreadable().is_empty());
+
+        // Folders that don't exist are not added.
+        assert_eq!(
+            LumenzaError::FileNotFound(),
+            library_new
+                .process_folder(&dir.path().join("typo"))
+                .unwrap_err()
+        );
+        assert_eq!(1, library_new.list_all_folders().len());
     }
 
     #[test]
@@ -94,7 +109,1824 @@ mod tests {
 
         library.generate_all_thumbnails().unwrap();
 
-        let thumbnail = thumbnails.join("lake.png");
-        std::fs::metadata(thumbnail).unwrap();
+        // Thumbnail paths are recorded in the database.
+        let pictures = library.list_all_pictures().unwrap();
+        for picture in pictures {
+            let thumbnail = picture.get_thumbnail();
+            assert!(thumbnail.starts_with(&thumbnails));
+            std::fs::metadata(thumbnail).unwrap();
+        }
+    }
+
+    #[test]
+    fn album_membership() {
+        let dir = TempDir::new("lumenza").unwrap();
+
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().join("thumbnails/");
+        let database = dir.path().join("database.sqlite3");
+
+        let library = Library::create(&config, &thumbnails, &database).unwrap();
+        let lake = library
+            .add_picture(&PathBuf::from("tests/images/lake.png"))
+            .unwrap();
+        let trail = library
+            .add_picture(&PathBuf::from("tests/images/trail.jpg"))
+            .unwrap();
+
+        let mut album = library.create_album("Holidays").unwrap();
+        library.add_to_album(&mut album, &lake).unwrap();
+        library.add_to_album(&mut album, &trail).unwrap();
+        assert_eq!(
+            library.add_to_album(&mut album, &lake),
+            Err(LumenzaError::PictureAlreadyInAlbum())
+        );
+
+        // Move the second picture to the front.
+        library.move_in_album(&album, &trail, 0).unwrap();
+        let pictures = library.list_album_pictures(&album).unwrap();
+        assert_eq!(pictures[0].get_id(), trail.get_id());
+        assert_eq!(pictures[1].get_id(), lake.get_id());
+
+        library.rename_album(&mut album, "Summer").unwrap();
+        let albums = library.list_albums().unwrap();
+        assert_eq!(1, albums.len());
+        assert_eq!("Summer", albums[0].get_name());
+        assert_eq!(2, albums[0].get_picture_count());
+
+        library.remove_from_album(&mut album, &trail).unwrap();
+        let pictures = library.list_album_pictures(&album).unwrap();
+        assert_eq!(1, pictures.len());
+        assert_eq!(pictures[0].get_id(), lake.get_id());
+        assert_eq!(1, album.get_picture_count());
+
+        // A stale copy of the album still ends up with the right count.
+        let mut stale = albums[0].clone();
+        library.remove_from_album(&mut stale, &lake).unwrap();
+        assert_eq!(0, stale.get_picture_count());
+        library.add_to_album(&mut album, &trail).unwrap();
+        assert_eq!(1, album.get_picture_count());
+
+        library.delete_album(album).unwrap();
+        assert!(library.list_albums().unwrap().is_empty());
+        assert_eq!(2, library.list_all_pictures().unwrap().len());
+    }
+
+    #[test]
+    fn album_cover() {
+        let dir = TempDir::new("lumenza").unwrap();
+
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().join("thumbnails/");
+        let database = dir.path().join("database.sqlite3");
+
+        let library = Library::create(&config, &thumbnails, &database).unwrap();
+        let lake = library
+            .add_picture(&PathBuf::from("tests/images/lake.png"))
+            .unwrap();
+        let trail = library
+            .add_picture(&PathBuf::from("tests/images/trail.jpg"))
+            .unwrap();
+
+        let mut album = library.create_album("Hikes").unwrap();
+        assert!(library.get_album_cover(&album).unwrap().is_none());
+        assert_eq!(
+            library.set_album_cover(&mut album, Some(&trail)),
+            Err(LumenzaError::PictureNotInAlbum())
+        );
+
+        // Without an explicit cover, the first picture is used.
+        library.add_to_album(&mut album, &lake).unwrap();
+        library.add_to_album(&mut album, &trail).unwrap();
+        let cover = library.get_album_cover(&album).unwrap().unwrap();
+        assert_eq!(cover.get_id(), lake.get_id());
+
+        library.set_album_cover(&mut album, Some(&trail)).unwrap();
+        let mut album = library.get_album(album.get_id()).unwrap();
+        assert_eq!(album.get_cover(), Some(trail.get_id()));
+
+        // Removing the cover from the album resets it.
+        library.remove_from_album(&mut album, &trail).unwrap();
+        assert_eq!(library.get_album(album.get_id()).unwrap().get_cover(), None);
+    }
+
+    #[test]
+    fn hierarchical_tags() {
+        let dir = TempDir::new("lumenza").unwrap();
+
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().join("thumbnails/");
+        let database = dir.path().join("database.sqlite3");
+
+        let library = Library::create(&config, &thumbnails, &database).unwrap();
+        let lake = library
+            .add_picture(&PathBuf::from("tests/images/lake.png"))
+            .unwrap();
+        let trail = library
+            .add_picture(&PathBuf::from("tests/images/trail.jpg"))
+            .unwrap();
+
+        let rome = library.create_tag("Places/Italy/Rome").unwrap();
+        assert_eq!("Rome", rome.get_name());
+        assert_eq!("Places/Italy/Rome", rome.get_path());
+        let italy = library.find_tag("Places/Italy").unwrap().unwrap();
+        assert_eq!(Some(italy.get_id()), rome.get_parent());
+        assert_eq!(3, library.list_tags().unwrap().len());
+
+        // Creating an existing tag returns it.
+        let again = library.create_tag("Places/Italy/Rome").unwrap();
+        assert_eq!(rome, again);
+        assert_eq!(
+            library.create_tag("Places//Rome"),
+            Err(LumenzaError::InvalidTagName())
+        );
+
+        library.tag_picture(&lake, &rome).unwrap();
+        library.tag_picture(&trail, &italy).unwrap();
+        assert_eq!(
+            1,
+            library.list_tagged_pictures(&italy, false).unwrap().len()
+        );
+        assert_eq!(2, library.list_tagged_pictures(&italy, true).unwrap().len());
+
+        let tags = library.list_picture_tags(&lake).unwrap();
+        assert_eq!(vec![rome.clone()], tags);
+
+        library.untag_picture(&lake, &rome).unwrap();
+        assert!(library.list_picture_tags(&lake).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rename_and_merge_tags() {
+        let dir = TempDir::new("lumenza").unwrap();
+
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().join("thumbnails/");
+        let database = dir.path().join("database.sqlite3");
+
+        let library = Library::create(&config, &thumbnails, &database).unwrap();
+        let lake = library
+            .add_picture(&PathBuf::from("tests/images/lake.png"))
+            .unwrap();
+        let trail = library
+            .add_picture(&PathBuf::from("tests/images/trail.jpg"))
+            .unwrap();
+
+        let mut italia = library.create_tag("Places/Italia").unwrap();
+        let rome = library.create_tag("Places/Italia/Rome").unwrap();
+        let italy = library.create_tag("Places/Italy").unwrap();
+        library.create_tag("Places/Italy/Rome").unwrap();
+
+        // Renaming follows through to descendants, but refuses duplicates.
+        assert_eq!(
+            library.rename_tag(&mut italia, "Italy"),
+            Err(LumenzaError::TagAlreadyExists())
+        );
+        library.rename_tag(&mut italia, "Italie").unwrap();
+        assert_eq!("Places/Italie", italia.get_path());
+        let rome = library.get_tag(rome.get_id()).unwrap();
+        assert_eq!("Places/Italie/Rome", rome.get_path());
+
+        library.tag_picture(&lake, &italia).unwrap();
+        library.tag_picture(&trail, &rome).unwrap();
+
+        // Merging moves pictures and children, combining children with the same name.
+        assert_eq!(
+            library.merge_tags(library.find_tag("Places").unwrap().unwrap(), &rome),
+            Err(LumenzaError::InvalidTagMerge())
+        );
+        library.merge_tags(italia, &italy).unwrap();
+        assert!(library.find_tag("Places/Italie").unwrap().is_none());
+        let paths: Vec<String> = library
+            .list_tags()
+            .unwrap()
+            .iter()
+            .map(|tag| tag.get_path())
+            .collect();
+        assert_eq!(vec!["Places", "Places/Italy", "Places/Italy/Rome"], paths);
+
+        let merged_rome = library.find_tag("Places/Italy/Rome").unwrap().unwrap();
+        assert_eq!(
+            1,
+            library.list_tagged_pictures(&italy, false).unwrap().len()
+        );
+        assert_eq!(
+            1,
+            library
+                .list_tagged_pictures(&merged_rome, false)
+                .unwrap()
+                .len()
+        );
+    }
+
+    #[test]
+    fn exif_metadata() {
+        let dir = TempDir::new("lumenza").unwrap();
+
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().join("thumbnails/");
+        let database = dir.path().join("database.sqlite3");
+
+        let library = Library::create(&config, &thumbnails, &database).unwrap();
+        let picture = library
+            .add_picture(&PathBuf::from("tests/fixtures/exif.jpg"))
+            .unwrap();
+        let metadata = library.get_metadata(&picture).unwrap();
+
+        let date = chrono::NaiveDate::from_ymd_opt(2023, 7, 14)
+            .unwrap()
+            .and_hms_opt(18, 32, 5)
+            .unwrap();
+        assert_eq!(Some(date), metadata.get_date_taken());
+        assert_eq!(Some("Canon".to_string()), metadata.get_camera_make());
+        assert_eq!(
+            Some("Canon EOS 5D Mark IV".to_string()),
+            metadata.get_camera_model()
+        );
+        assert_eq!(Some("EF50mm f/1.8 STM".to_string()), metadata.get_lens());
+        assert_eq!(Some(0.004), metadata.get_exposure_time());
+        assert_eq!(Some(2.8), metadata.get_f_number());
+        assert_eq!(Some(200), metadata.get_iso());
+        assert_eq!(Some(50.0), metadata.get_focal_length());
+        assert_eq!(Some(6), metadata.get_orientation());
+        assert!((metadata.get_latitude().unwrap() - 41.89).abs() < 0.0001);
+        assert!((metadata.get_longitude().unwrap() - 12.4916).abs() < 0.0001);
+        assert_eq!(Some(60), metadata.get_width());
+        assert_eq!(Some(40), metadata.get_height());
+
+        // Pictures without EXIF still get their dimensions.
+        let lake = library
+            .add_picture(&PathBuf::from("tests/images/lake.png"))
+            .unwrap();
+        let metadata = library.get_metadata(&lake).unwrap();
+        assert_eq!(None, metadata.get_date_taken());
+        assert!(metadata.get_width().is_some());
+    }
+
+    #[test]
+    fn filter_pictures() {
+        let dir = TempDir::new("lumenza").unwrap();
+
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().join("thumbnails/");
+        let database = dir.path().join("database.sqlite3");
+
+        let mut library = Library::create(&config, &thumbnails, &database).unwrap();
+        library
+            .process_folder(&PathBuf::from("tests/images/"))
+            .unwrap();
+        let canon = library
+            .add_picture(&PathBuf::from("tests/fixtures/exif.jpg"))
+            .unwrap();
+        let lake = library
+            .query(&Query::new().filter(Filter::Text("LAKE".to_string())))
+            .unwrap();
+        assert_eq!(1, lake.len());
+        let lake = &lake[0];
+
+        library.set_rating(lake, 4).unwrap();
+        library.set_rating(&canon, 2).unwrap();
+        assert_eq!(
+            library.set_rating(lake, 6),
+            Err(LumenzaError::InvalidRating())
+        );
+        assert_eq!(4, library.get_rating(lake).unwrap());
+
+        let rome = library.create_tag("Places/Italy/Rome").unwrap();
+        let italy = library.find_tag("Places/Italy").unwrap().unwrap();
+        library.tag_picture(&canon, &rome).unwrap();
+
+        let count = |filter: Filter| library.count(&filter).unwrap();
+        assert_eq!(3, count(Filter::All));
+        assert_eq!(2, count(Filter::folder(&PathBuf::from("tests/images"))));
+        assert_eq!(1, count(Filter::tag(&italy)));
+        assert_eq!(1, count(Filter::MinRating(3)));
+        assert_eq!(2, count(Filter::MinRating(1) & Filter::MaxRating(4)));
+        assert_eq!(2, count(Filter::MimeType("image/jpeg".to_string())));
+        assert_eq!(3, count(Filter::MimeType("image/*".to_string())));
+        assert_eq!(1, count(Filter::Camera("eos 5d".to_string())));
+        assert_eq!(2, count(!Filter::Camera("canon".to_string())));
+        assert_eq!(1, count(Filter::MaxDimensions(100, 100)));
+        assert_eq!(1, count(Filter::Text("rome".to_string())));
+        assert_eq!(
+            2,
+            count(Filter::Text("lake".to_string()) | Filter::Text("exif".to_string()))
+        );
+
+        let from = chrono::NaiveDate::from_ymd_opt(2023, 1, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0);
+        let to = chrono::NaiveDate::from_ymd_opt(2023, 12, 31)
+            .unwrap()
+            .and_hms_opt(0, 0, 0);
+        assert_eq!(1, count(Filter::DateTaken(from, to)));
+        assert_eq!(0, count(Filter::DateTaken(None, from)));
+
+        let mut album = library.create_album("Best").unwrap();
+        library.add_to_album(&mut album, lake).unwrap();
+        assert_eq!(1, count(Filter::album(&album) & Filter::MinRating(4)));
+        assert_eq!(0, count(Filter::album(&album) & !Filter::MinRating(4)));
+    }
+
+    #[test]
+    fn sort_and_paginate_pictures() {
+        let dir = TempDir::new("lumenza").unwrap();
+
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().join("thumbnails/");
+        let database = dir.path().join("database.sqlite3");
+
+        let library = Library::create(&config, &thumbnails, &database).unwrap();
+        let trail = library
+            .add_picture(&PathBuf::from("tests/images/trail.jpg"))
+            .unwrap();
+        let lake = library
+            .add_picture(&PathBuf::from("tests/images/lake.png"))
+            .unwrap();
+        let canon = library
+            .add_picture(&PathBuf::from("tests/fixtures/exif.jpg"))
+            .unwrap();
+        library.set_rating(&lake, 5).unwrap();
+        library.set_rating(&trail, 1).unwrap();
+
+        let ids = |query: Query| -> Vec<u32> {
+            let pictures = library.query(&query).unwrap();
+            pictures.iter().map(|picture| picture.get_id()).collect()
+        };
+        assert_eq!(
+            vec![trail.get_id(), lake.get_id(), canon.get_id()],
+            ids(Query::new())
+        );
+        assert_eq!(
+            vec![lake.get_id(), trail.get_id(), canon.get_id()],
+            ids(Query::new().sort(Sort::Rating, Order::Descending))
+        );
+        // Pictures without a capture date come last.
+        assert_eq!(
+            vec![canon.get_id(), trail.get_id(), lake.get_id()],
+            ids(Query::new().sort(Sort::DateTaken, Order::Descending))
+        );
+        assert_eq!(
+            vec![canon.get_id(), lake.get_id(), trail.get_id()],
+            ids(Query::new().sort(Sort::Filename, Order::Ascending))
+        );
+        assert_eq!(
+            vec![lake.get_id()],
+            ids(Query::new()
+                .sort(Sort::Filename, Order::Ascending)
+                .offset(1)
+                .limit(1))
+        );
+    }
+
+    #[test]
+    fn scan_videos() {
+        let dir = TempDir::new("lumenza").unwrap();
+
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().join("thumbnails/");
+        let database = dir.path().join("database.sqlite3");
+
+        let mut library = Library::create(&config, &thumbnails, &database).unwrap();
+        library
+            .process_folder(&PathBuf::from("tests/fixtures/videos/"))
+            .unwrap();
+        library
+            .process_folder(&PathBuf::from("tests/images/"))
+            .unwrap();
+
+        assert_eq!(2, library.list_all_pictures().unwrap().len());
+        assert_eq!(2, library.list_all_videos().unwrap().len());
+        let media = library.list_all_media().unwrap();
+        assert_eq!(4, media.len());
+        let videos: Vec<_> = media
+            .iter()
+            .filter_map(|media| media.get_video_info())
+            .collect();
+        assert_eq!(2, videos.len());
+        assert!(videos.iter().all(|info| info.get_duration().is_some()));
+        let query = Query::new().filter(Filter::Kind(MediaKind::Video));
+        assert_eq!(2, library.query(&query).unwrap().len());
+
+        let lake = &library.list_all_pictures().unwrap()[0];
+        assert_eq!(MediaKind::Picture, lake.get_kind());
+        assert_eq!(None, library.get_video_info(lake).unwrap());
+        let media = library.get_media(lake.get_id()).unwrap();
+        assert_eq!(MediaKind::Picture, media.get_kind());
+        assert_eq!(lake.get_id(), media.get_entry().get_id());
+    }
+
+    #[test]
+    fn video_container_metadata() {
+        let dir = TempDir::new("lumenza").unwrap();
+
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().join("thumbnails/");
+        let database = dir.path().join("database.sqlite3");
+
+        let library = Library::create(&config, &thumbnails, &database).unwrap();
+
+        let mp4 = library
+            .add_picture(&PathBuf::from("tests/fixtures/videos/clip.mp4"))
+            .unwrap();
+        assert_eq!(MediaKind::Video, mp4.get_kind());
+        let info = library.get_video_info(&mp4).unwrap().unwrap();
+        let date = chrono::NaiveDate::from_ymd_opt(2023, 8, 1)
+            .unwrap()
+            .and_hms_opt(12, 0, 0);
+        assert_eq!(Some(12.5), info.get_duration());
+        assert_eq!(Some(1920), info.get_width());
+        assert_eq!(Some(1080), info.get_height());
+        assert_eq!(Some("avc1".to_string()), info.get_codec());
+        assert_eq!(date, info.get_creation_time());
+        // The creation time also serves as the capture date.
+        assert_eq!(date, library.get_metadata(&mp4).unwrap().get_date_taken());
+
+        let mkv = library
+            .add_picture(&PathBuf::from("tests/fixtures/videos/clip.mkv"))
+            .unwrap();
+        let info = library.get_video_info(&mkv).unwrap().unwrap();
+        let date = chrono::NaiveDate::from_ymd_opt(2022, 5, 10)
+            .unwrap()
+            .and_hms_opt(8, 30, 0);
+        assert_eq!(Some(4.0), info.get_duration());
+        assert_eq!(Some(640), info.get_width());
+        assert_eq!(Some(360), info.get_height());
+        assert_eq!(Some("V_VP9".to_string()), info.get_codec());
+        assert_eq!(date, info.get_creation_time());
+
+        // A creation time too large for a timestamp is ignored.
+        let mut mvhd = vec![1, 0, 0, 0];
+        mvhd.extend(u64::MAX.to_be_bytes());
+        mvhd.extend(0u64.to_be_bytes());
+        mvhd.extend(1000u32.to_be_bytes());
+        mvhd.extend(2000u64.to_be_bytes());
+        let mut mp4 = Vec::new();
+        mp4.extend(16u32.to_be_bytes());
+        mp4.extend(b"ftypisom\0\0\0\0");
+        mp4.extend((16 + mvhd.len() as u32).to_be_bytes());
+        mp4.extend(b"moov");
+        mp4.extend((8 + mvhd.len() as u32).to_be_bytes());
+        mp4.extend(b"mvhd");
+        mp4.extend(mvhd);
+        let garbage = dir.path().join("garbage.mp4");
+        std::fs::write(&garbage, mp4).unwrap();
+        let garbage = library.add_picture(&garbage).unwrap();
+        let info = library.get_video_info(&garbage).unwrap().unwrap();
+        assert_eq!(Some(2.0), info.get_duration());
+        assert_eq!(None, info.get_creation_time());
+    }
+
+    /// Write a config file pointing to the given database, as Library::create
+    /// would, without touching the database itself.
+    fn write_config(dir: &path::Path, database: &path::Path) -> PathBuf {
+        let config = dir.join("default.conf");
+        let text = format!(
+            "folders_paths = []\nthumbnails_path = {:?}\ndatabase_path = {:?}\n",
+            dir.join("thumbnails/"),
+            database
+        );
+        std::fs::write(&config, text).unwrap();
+        config
+    }
+
+    fn schema_version(database: &path::Path) -> u32 {
+        let connection = rusqlite::Connection::open(database).unwrap();
+        connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn upgrade_v1_database() {
+        let dir = TempDir::new("lumenza").unwrap();
+
+        let database = dir.path().join("database.sqlite3");
+        std::fs::copy("tests/fixtures/v1.sqlite3", &database).unwrap();
+        let config = write_config(dir.path(), &database);
+        assert_eq!(0, schema_version(&database));
+
+        let library = Library::open(&config).unwrap();
+
+        // The database is now at the same version as a new one.
+        let fresh = dir.path().join("fresh.sqlite3");
+        Library::create(
+            &dir.path().join("fresh.conf"),
+            &dir.path().join("t"),
+            &fresh,
+        )
+        .unwrap();
+        assert_eq!(schema_version(&fresh), schema_version(&database));
+
+        // Existing pictures survive, and the new tables can be used.
+        let pictures = library.list_all_pictures().unwrap();
+        assert_eq!(2, pictures.len());
+        assert_eq!(
+            PathBuf::from("/home/user/.cache/lumenza/trail.jpg"),
+            pictures[1].get_thumbnail()
+        );
+        // Their mime type is guessed from their extension.
+        assert_eq!("image/jpeg", pictures[1].get_mime());
+        assert!(pictures.iter().all(|p| p.get_kind() == MediaKind::Picture));
+        let query = Query::new().filter(Filter::MimeType("image/jpeg".to_string()));
+        assert_eq!(1, library.query(&query).unwrap().len());
+        let mut album = library.create_album("Old pictures").unwrap();
+        library.add_to_album(&mut album, &pictures[0]).unwrap();
+        let tag = library.create_tag("Imported").unwrap();
+        library.tag_picture(&pictures[1], &tag).unwrap();
+        library.set_rating(&pictures[1], 3).unwrap();
+        assert_eq!(0, library.get_rating(&pictures[0]).unwrap());
+
+        // Opening again is a no-op.
+        drop(library);
+        Library::open(&config).unwrap();
+    }
+
+    #[test]
+    fn refuse_newer_database() {
+        let dir = TempDir::new("lumenza").unwrap();
+
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().join("thumbnails/");
+        let database = dir.path().join("database.sqlite3");
+
+        Library::create(&config, &thumbnails, &database).unwrap();
+        let connection = rusqlite::Connection::open(&database).unwrap();
+        connection.pragma_update(None, "user_version", 999).unwrap();
+        drop(connection);
+
+        match Library::open(&config) {
+            Err(LumenzaError::DatabaseTooNew(999, latest)) => assert!(latest < 999),
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => panic!("opened a database from the future"),
+        }
+    }
+
+    #[test]
+    fn find_duplicates() {
+        let dir = TempDir::new("lumenza").unwrap();
+
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().join("thumbnails/");
+        let database = dir.path().join("database.sqlite3");
+
+        // The same picture, synced from two devices.
+        let phone = dir.path().join("phone");
+        let laptop = dir.path().join("laptop");
+        std::fs::create_dir_all(&phone).unwrap();
+        std::fs::create_dir_all(&laptop).unwrap();
+        std::fs::copy("tests/images/lake.png", phone.join("IMG_0001.png")).unwrap();
+        std::fs::copy("tests/images/lake.png", laptop.join("lake copy.png")).unwrap();
+        std::fs::copy("tests/images/trail.jpg", laptop.join("trail.jpg")).unwrap();
+
+        let mut library = Library::create(&config, &thumbnails, &database).unwrap();
+        library.process_folder(&phone).unwrap();
+        library.process_folder(&laptop).unwrap();
+
+        let duplicates = library.find_duplicates().unwrap();
+        assert_eq!(1, duplicates.len());
+        let mut names: Vec<PathBuf> = duplicates[0]
+            .iter()
+            .map(|picture| PathBuf::from(picture.get_filename().file_name().unwrap()))
+            .collect();
+        names.sort();
+        assert_eq!(
+            vec![
+                PathBuf::from("IMG_0001.png"),
+                PathBuf::from("lake copy.png")
+            ],
+            names
+        );
+        assert_eq!(duplicates[0][0].get_hash(), duplicates[0][1].get_hash());
+        assert!(duplicates[0][0].get_hash().is_some());
+
+        // Once a copy is deleted, the other one is no longer a duplicate.
+        std::fs::remove_file(laptop.join("lake copy.png")).unwrap();
+        library.verify().unwrap();
+        assert!(library.find_duplicates().unwrap().is_empty());
+    }
+
+    #[test]
+    fn detect_moved_pictures() {
+        let dir = TempDir::new("lumenza").unwrap();
+
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().join("thumbnails/");
+        let database = dir.path().join("database.sqlite3");
+
+        let pictures = dir.path().join("pictures");
+        std::fs::create_dir_all(&pictures).unwrap();
+        std::fs::copy("tests/images/lake.png", pictures.join("lake.png")).unwrap();
+        std::fs::copy("tests/images/trail.jpg", pictures.join("trail.jpg")).unwrap();
+
+        let mut library = Library::create(&config, &thumbnails, &database).unwrap();
+        library.process_folder(&pictures).unwrap();
+        let lake = library.list_all_pictures().unwrap().remove(0);
+        let tag = library.create_tag("Places/Lakes").unwrap();
+        library.tag_picture(&lake, &tag).unwrap();
+        let mut album = library.create_album("Holidays").unwrap();
+        library.add_to_album(&mut album, &lake).unwrap();
+
+        // Rename the picture and move it into a subfolder.
+        let moved = pictures.join("2023").join("IMG_0042.png");
+        std::fs::create_dir_all(moved.parent().unwrap()).unwrap();
+        std::fs::rename(pictures.join("lake.png"), &moved).unwrap();
+        library.process_folder(&pictures).unwrap();
+
+        let all = library.list_all_pictures().unwrap();
+        assert_eq!(2, all.len());
+        let found = all.iter().find(|p| p.get_id() == lake.get_id()).unwrap();
+        assert_eq!(moved, found.get_filename());
+        assert_eq!(lake.get_hash(), found.get_hash());
+        assert_eq!(vec![tag], library.list_picture_tags(found).unwrap());
+        let members = library.list_album_pictures(&album).unwrap();
+        assert_eq!(
+            vec![lake.get_id()],
+            members.iter().map(|p| p.get_id()).collect::<Vec<_>>()
+        );
+
+        // Of two identical copies gone at once, the one with the same
+        // modification time is the one that was moved.
+        let copies = dir.path().join("copies");
+        std::fs::create_dir_all(&copies).unwrap();
+        for (name, age) in [("first.jpg", 100), ("second.jpg", 200)] {
+            std::fs::copy("tests/images/trail.jpg", copies.join(name)).unwrap();
+            let file = std::fs::File::options()
+                .write(true)
+                .open(copies.join(name))
+                .unwrap();
+            let time = std::time::SystemTime::now() - std::time::Duration::from_secs(age);
+            file.set_modified(time).unwrap();
+        }
+        library.process_folder(&copies).unwrap();
+        let second = library
+            .query(&Query::new().filter(Filter::folder(&copies)))
+            .unwrap()
+            .into_iter()
+            .find(|p| p.get_filename().ends_with("second.jpg"))
+            .unwrap();
+        std::fs::remove_file(copies.join("first.jpg")).unwrap();
+        std::fs::rename(copies.join("second.jpg"), copies.join("third.jpg")).unwrap();
+        library.process_folder(&copies).unwrap();
+        let third = library.get_picture(second.get_id()).unwrap();
+        assert_eq!(copies.join("third.jpg"), third.get_filename());
+    }
+
+    #[test]
+    fn verify_missing_pictures() {
+        let dir = TempDir::new("lumenza").unwrap();
+
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().join("thumbnails/");
+        let database = dir.path().join("database.sqlite3");
+
+        // Pictures on an external drive, which gets unplugged.
+        let drive = dir.path().join("drive");
+        let unplugged = dir.path().join("unplugged");
+        std::fs::create_dir_all(&drive).unwrap();
+        std::fs::copy("tests/images/lake.png", drive.join("lake.png")).unwrap();
+        std::fs::copy("tests/images/trail.jpg", drive.join("trail.jpg")).unwrap();
+
+        let mut library = Library::create(&config, &thumbnails, &database).unwrap();
+        library.process_folder(&drive).unwrap();
+        let lake = library.list_all_pictures().unwrap().remove(0);
+        let tag = library.create_tag("Lakes").unwrap();
+        library.tag_picture(&lake, &tag).unwrap();
+        let mut album = library.create_album("Holidays").unwrap();
+        library.add_to_album(&mut album, &lake).unwrap();
+
+        std::fs::rename(&drive, &unplugged).unwrap();
+        let report = library.verify().unwrap();
+        assert_eq!(2, report.get_missing().len());
+        assert!(report.get_missing().iter().all(|p| p.is_missing()));
+        assert!(report.get_reattached().is_empty());
+        assert!(library.list_all_pictures().unwrap().is_empty());
+        assert_eq!(2, library.count(&Filter::Missing).unwrap());
+
+        // Metadata is kept while the drive is away, and comes back with it.
+        std::fs::rename(&unplugged, &drive).unwrap();
+        let report = library.verify().unwrap();
+        assert!(report.get_missing().is_empty());
+        assert_eq!(2, report.get_reattached().len());
+        let pictures = library.list_all_pictures().unwrap();
+        assert_eq!(2, pictures.len());
+        assert_eq!(
+            vec![tag.clone()],
+            library.list_picture_tags(&pictures[0]).unwrap()
+        );
+
+        // Rescanning the folder also reattaches pictures.
+        std::fs::rename(&drive, &unplugged).unwrap();
+        library.verify().unwrap();
+        std::fs::rename(&unplugged, &drive).unwrap();
+        library.process_folder(&drive).unwrap();
+        assert!(library.list_missing().unwrap().is_empty());
+
+        // Only missing pictures are purged.
+        std::fs::remove_file(drive.join("lake.png")).unwrap();
+        let report = library.verify().unwrap();
+        assert_eq!(
+            vec![lake.get_id()],
+            report
+                .get_missing()
+                .iter()
+                .map(|p| p.get_id())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(1, library.purge_missing().unwrap());
+        assert!(library.list_missing().unwrap().is_empty());
+        assert_eq!(1, library.list_all_pictures().unwrap().len());
+        assert_eq!(
+            0,
+            library
+                .get_album(album.get_id())
+                .unwrap()
+                .get_picture_count()
+        );
+        assert!(library.list_tagged_pictures(&tag, true).unwrap().is_empty());
+    }
+
+    #[test]
+    fn update_picture() {
+        let dir = TempDir::new("lumenza").unwrap();
+
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().join("thumbnails/");
+        let database = dir.path().join("database.sqlite3");
+
+        let pictures = dir.path().join("pictures");
+        std::fs::create_dir_all(&pictures).unwrap();
+        std::fs::copy("tests/images/lake.png", pictures.join("lake.png")).unwrap();
+        std::fs::copy("tests/images/trail.jpg", pictures.join("trail.jpg")).unwrap();
+
+        let mut library = Library::create(&config, &thumbnails, &database).unwrap();
+        library.process_folder(&pictures).unwrap();
+        let mut lake = library.list_all_pictures().unwrap().remove(0);
+
+        // The new file must exist.
+        let renamed = pictures.join("lake at dawn.png");
+        assert_eq!(
+            Err(LumenzaError::FileNotFound()),
+            lake.set_filename(&renamed)
+        );
+        std::fs::rename(pictures.join("lake.png"), &renamed).unwrap();
+        lake.set_filename(&renamed).unwrap();
+        lake.set_thumbnail(&dir.path().join("custom.png"));
+        library.update_picture(&lake).unwrap();
+
+        // Another picture can't take the same filename.
+        let mut trail = library.list_all_pictures().unwrap().remove(1);
+        trail.set_filename(&renamed).unwrap();
+        assert_eq!(
+            Err(LumenzaError::PictureAlreadyInLibrary()),
+            library.update_picture(&trail)
+        );
+
+        // Changes are saved across sessions.
+        drop(library);
+        let library = Library::open(&config).unwrap();
+        let saved = library.get_picture(lake.get_id()).unwrap();
+        assert_eq!(renamed, saved.get_filename());
+        assert_eq!(dir.path().join("custom.png"), saved.get_thumbnail());
+    }
+
+    #[test]
+    fn thumbnail_cache() {
+        let dir = TempDir::new("lumenza").unwrap();
+
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().join("thumbnails/");
+        let database = dir.path().join("database.sqlite3");
+
+        // Two cameras using the same file names.
+        let pictures = dir.path().join("pictures");
+        std::fs::create_dir_all(pictures.join("a")).unwrap();
+        std::fs::create_dir_all(pictures.join("b")).unwrap();
+        std::fs::copy("tests/images/trail.jpg", pictures.join("a/IMG_0001.jpg")).unwrap();
+        std::fs::copy("tests/fixtures/exif.jpg", pictures.join("b/IMG_0001.jpg")).unwrap();
+
+        let mut library = Library::create(&config, &thumbnails, &database).unwrap();
+        library.process_folder(&pictures).unwrap();
+        library.generate_all_thumbnails().unwrap();
+
+        let thumbnail_of = |library: &Library, name: &str| {
+            let all = library.list_all_pictures().unwrap();
+            let picture = all.iter().find(|p| p.get_filename() == pictures.join(name));
+            picture.unwrap().get_thumbnail()
+        };
+        let a = thumbnail_of(&library, "a/IMG_0001.jpg");
+        let b = thumbnail_of(&library, "b/IMG_0001.jpg");
+        assert_ne!(a, b);
+        assert!(a.is_file() && b.is_file());
+
+        // Up to date thumbnails are not generated again, and unused files
+        // are cleaned up.
+        let modified = std::fs::metadata(&a).unwrap().modified().unwrap();
+        let orphan = thumbnails
+            .join("00")
+            .join(format!("{}-256.jpg", "0".repeat(64)));
+        std::fs::create_dir_all(orphan.parent().unwrap()).unwrap();
+        std::fs::write(&orphan, b"").unwrap();
+        library.generate_all_thumbnails().unwrap();
+        assert_eq!(modified, std::fs::metadata(&a).unwrap().modified().unwrap());
+        assert!(!orphan.exists());
+
+        // Editing a picture gives it a new thumbnail, here the same as the
+        // other camera's since the content is now identical.
+        let edited = pictures.join("b/IMG_0001.jpg");
+        std::fs::copy("tests/images/trail.jpg", &edited).unwrap();
+        let later = std::time::SystemTime::now() + std::time::Duration::from_secs(10);
+        std::fs::File::options()
+            .write(true)
+            .open(&edited)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        library.generate_all_thumbnails().unwrap();
+        assert_eq!(a, thumbnail_of(&library, "b/IMG_0001.jpg"));
+        assert_eq!(a, thumbnail_of(&library, "a/IMG_0001.jpg"));
+        let all = library.list_all_pictures().unwrap();
+        assert_eq!(all[0].get_hash(), all[1].get_hash());
+        assert!(!b.exists());
+    }
+
+    #[test]
+    fn thumbnail_progress() {
+        let dir = TempDir::new("lumenza").unwrap();
+
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().join("thumbnails/");
+        let database = dir.path().join("database.sqlite3");
+
+        let pictures = dir.path().join("pictures");
+        std::fs::create_dir_all(&pictures).unwrap();
+        std::fs::copy("tests/images/lake.png", pictures.join("lake.png")).unwrap();
+        std::fs::copy("tests/images/trail.jpg", pictures.join("trail.jpg")).unwrap();
+        // A JPEG cut short after its signature.
+        std::fs::write(pictures.join("broken.jpg"), b"\xff\xd8\xff\xe0 broken").unwrap();
+
+        // Nothing is done once cancelled.
+        let mut library = Library::create(&config, &thumbnails, &database).unwrap();
+        library.process_folder(&pictures).unwrap();
+        let cancel = CancelToken::new();
+        cancel.clone().cancel();
+        let report = library.generate_thumbnails(|_, _| (), &cancel).unwrap();
+        assert!(report.is_cancelled());
+        assert_eq!(0, report.get_generated());
+
+        // A broken picture doesn't stop the others.
+        let mut calls = Vec::new();
+        let report = library
+            .generate_thumbnails(|done, total| calls.push((done, total)), &CancelToken::new())
+            .unwrap();
+        assert_eq!(vec![(1, 3), (2, 3), (3, 3)], calls);
+        assert!(!report.is_cancelled());
+        assert_eq!(2, report.get_generated());
+        assert_eq!(0, report.get_skipped());
+        let (broken, err) = &report.get_failed()[0];
+        assert_eq!(pictures.join("broken.jpg"), broken.get_filename());
+        assert_eq!(&LumenzaError::ImageError(), err);
+
+        // Only pictures without an up to date thumbnail are processed again.
+        let report = library.generate_all_thumbnails().unwrap();
+        assert_eq!(0, report.get_generated());
+        assert_eq!(2, report.get_skipped());
+        assert_eq!(1, report.get_failed().len());
+    }
+
+    #[test]
+    fn thumbnail_profiles() {
+        let dir = TempDir::new("lumenza").unwrap();
+
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().join("thumbnails/");
+        let database = dir.path().join("database.sqlite3");
+
+        let mut library = Library::create(&config, &thumbnails, &database).unwrap();
+        assert_eq!(
+            vec![ThumbnailProfile::default()],
+            library.get_thumbnail_profiles()
+        );
+
+        let grid = ThumbnailProfile::new(128, ThumbnailFormat::Jpeg, 80);
+        let preview = ThumbnailProfile::new(512, ThumbnailFormat::WebP, 100);
+        for invalid in [
+            vec![],
+            vec![grid, ThumbnailProfile::new(128, ThumbnailFormat::Png, 100)],
+            vec![ThumbnailProfile::new(128, ThumbnailFormat::Jpeg, 0)],
+        ] {
+            assert_eq!(
+                Err(LumenzaError::InvalidThumbnailProfile()),
+                library.set_thumbnail_profiles(&invalid)
+            );
+        }
+        library.set_thumbnail_profiles(&[grid, preview]).unwrap();
+
+        library
+            .process_folder(&PathBuf::from("tests/images/"))
+            .unwrap();
+        library.generate_all_thumbnails().unwrap();
+
+        let lake = library.list_all_pictures().unwrap().remove(0);
+        let small = lake.thumbnail_for(100);
+        let large = lake.thumbnail_for(300);
+        assert_eq!(small, lake.get_thumbnail());
+        assert_eq!(large, lake.thumbnail_for(2000));
+        assert_eq!(Some("jpg".as_ref()), small.extension());
+        assert_eq!(Some("webp".as_ref()), large.extension());
+        assert_eq!(128, image::image_dimensions(&small).unwrap().0);
+        assert_eq!(512, image::image_dimensions(&large).unwrap().0);
+
+        // Profiles are kept in the config, and thumbnails of profiles no
+        // longer used are deleted.
+        drop(library);
+        let mut library = Library::open(&config).unwrap();
+        assert_eq!(vec![grid, preview], library.get_thumbnail_profiles());
+        library.set_thumbnail_profiles(&[preview]).unwrap();
+        drop(library);
+        let library = Library::open(&config).unwrap();
+        library.generate_all_thumbnails().unwrap();
+        let lake = library.list_all_pictures().unwrap().remove(0);
+        assert_eq!(large, lake.get_thumbnail());
+        assert_eq!(large, lake.thumbnail_for(100));
+        assert!(!small.exists());
+    }
+
+    #[test]
+    fn thumbnail_orientation_and_crop() {
+        let dir = TempDir::new("lumenza").unwrap();
+
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().join("thumbnails/");
+        let database = dir.path().join("database.sqlite3");
+
+        // A plain panorama, with all the detail on the right.
+        let pictures = dir.path().join("pictures");
+        std::fs::create_dir_all(&pictures).unwrap();
+        let panorama = image::RgbImage::from_fn(300, 100, |x, y| match x < 200 {
+            true => image::Rgb([128, 128, 128]),
+            false => match (x / 10 + y / 10) % 2 {
+                0 => image::Rgb([0, 0, 0]),
+                _ => image::Rgb([255, 255, 255]),
+            },
+        });
+        panorama.save(pictures.join("panorama.png")).unwrap();
+        std::fs::copy("tests/images/lake.png", pictures.join("lake.png")).unwrap();
+        std::fs::copy("tests/fixtures/exif.jpg", pictures.join("rotated.jpg")).unwrap();
+
+        let mut library = Library::create(&config, &thumbnails, &database).unwrap();
+        let png = |size| ThumbnailProfile::new(size, ThumbnailFormat::Png, 100);
+        library
+            .set_thumbnail_profiles(&[
+                png(128),
+                png(64).crop(ThumbnailCrop::Center),
+                png(100).crop(ThumbnailCrop::Smart),
+                png(128).crop(ThumbnailCrop::Center),
+            ])
+            .unwrap();
+        library.process_folder(&pictures).unwrap();
+        let report = library.generate_all_thumbnails().unwrap();
+        assert_eq!(3, report.get_generated());
+
+        let all = library.list_all_pictures().unwrap();
+        let picture = |name: &str| {
+            let filename = pictures.join(name);
+            all.iter().find(|p| p.get_filename() == filename).unwrap()
+        };
+        let dimensions = |name: &str, size, crop| {
+            image::image_dimensions(picture(name).cropped_thumbnail_for(size, crop)).unwrap()
+        };
+
+        // Landscapes fit the box, and are never enlarged.
+        assert_eq!((128, 73), dimensions("lake.png", 128, ThumbnailCrop::Fit));
+        assert_eq!((64, 64), dimensions("lake.png", 64, ThumbnailCrop::Center));
+        // Profiles of the same size are told apart by their crop, and
+        // thumbnail_for() prefers the whole picture.
+        assert_eq!(
+            (128, 128),
+            dimensions("lake.png", 128, ThumbnailCrop::Center)
+        );
+        assert_eq!(
+            picture("lake.png").cropped_thumbnail_for(64, ThumbnailCrop::Fit),
+            picture("lake.png").thumbnail_for(64)
+        );
+        // The camera was held sideways, the 60x40 picture is a portrait.
+        assert_eq!((40, 60), dimensions("rotated.jpg", 128, ThumbnailCrop::Fit));
+
+        // The smart crop keeps the detailed part.
+        let tile =
+            image::open(picture("panorama.png").cropped_thumbnail_for(100, ThumbnailCrop::Smart))
+                .unwrap()
+                .to_rgb8();
+        assert_eq!((100, 100), tile.dimensions());
+        assert!(tile.pixels().all(|p| p.0[0] == 0 || p.0[0] == 255));
+    }
+
+    /// Thumbnailer of a platform that only draws gray squares.
+    struct GrayThumbnailer {
+        calls: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl Thumbnailer for GrayThumbnailer {
+        fn generate(
+            &self,
+            _picture: &Picture,
+            thumbnail: &path::Path,
+            profile: &ThumbnailProfile,
+        ) -> Result<(), LumenzaError> {
+            self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            let size = profile.get_size();
+            image::GrayImage::from_pixel(size, size, image::Luma([128]))
+                .save_with_format(thumbnail, image::ImageFormat::Png)
+                .map_err(|_| LumenzaError::ImageError())
+        }
+    }
+
+    #[test]
+    fn custom_thumbnailer() {
+        let dir = TempDir::new("lumenza").unwrap();
+
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().join("thumbnails/");
+        let database = dir.path().join("database.sqlite3");
+
+        let mut library = Library::create(&config, &thumbnails, &database).unwrap();
+        let calls = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        library.set_thumbnailer(GrayThumbnailer {
+            calls: calls.clone(),
+        });
+        library
+            .set_thumbnail_profiles(&[ThumbnailProfile::new(48, ThumbnailFormat::Png, 100)])
+            .unwrap();
+        library
+            .process_folder(&PathBuf::from("tests/images/"))
+            .unwrap();
+
+        // Lumenza still takes care of caching and bookkeeping.
+        library.generate_all_thumbnails().unwrap();
+        library.generate_all_thumbnails().unwrap();
+        assert_eq!(2, calls.load(std::sync::atomic::Ordering::SeqCst));
+        for picture in library.list_all_pictures().unwrap() {
+            let thumbnail = image::open(picture.get_thumbnail()).unwrap().to_luma8();
+            assert_eq!((48, 48), thumbnail.dimensions());
+            assert_eq!(128, thumbnail.get_pixel(0, 0).0[0]);
+        }
+    }
+
+    #[test]
+    fn embedded_preview_thumbnails() {
+        let dir = TempDir::new("lumenza").unwrap();
+
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().join("thumbnails/");
+        let database = dir.path().join("database.sqlite3");
+
+        // A blue 600x400 picture, taken sideways, whose EXIF data holds a red
+        // 300x200 preview.
+        let pictures = dir.path().join("pictures");
+        std::fs::create_dir_all(&pictures).unwrap();
+        std::fs::copy("tests/fixtures/preview.jpg", pictures.join("preview.jpg")).unwrap();
+
+        let mut library = Library::create(&config, &thumbnails, &database).unwrap();
+        let png = |size| ThumbnailProfile::new(size, ThumbnailFormat::Png, 100);
+        library
+            .set_thumbnail_profiles(&[png(128), png(512)])
+            .unwrap();
+        library.process_folder(&pictures).unwrap();
+        library.generate_all_thumbnails().unwrap();
+
+        let picture = library.list_all_pictures().unwrap().remove(0);
+        let is_red = |thumbnail: &image::RgbImage| {
+            let pixel = thumbnail.get_pixel(thumbnail.width() / 2, thumbnail.height() / 2);
+            pixel.0[0] > 150 && pixel.0[2] < 100
+        };
+
+        // The preview is large enough for small thumbnails.
+        let small = image::open(picture.thumbnail_for(128)).unwrap().to_rgb8();
+        assert_eq!((85, 128), small.dimensions());
+        assert!(is_red(&small));
+
+        // Larger ones need the picture itself.
+        let large = image::open(picture.thumbnail_for(512)).unwrap().to_rgb8();
+        assert_eq!((341, 512), large.dimensions());
+        assert!(!is_red(&large));
+    }
+
+    #[test]
+    fn camera_raw_files() {
+        let dir = TempDir::new("lumenza").unwrap();
+
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().join("thumbnails/");
+        let database = dir.path().join("database.sqlite3");
+
+        let mut library = Library::create(&config, &thumbnails, &database).unwrap();
+        library
+            .process_folder(&PathBuf::from("tests/fixtures/raw/"))
+            .unwrap();
+
+        let pictures = library.list_all_pictures().unwrap();
+        assert_eq!(3, pictures.len());
+        assert!(pictures.iter().all(|p| p.is_raw()));
+        assert_eq!(3, library.count(&Filter::Raw).unwrap());
+        let picture = |name: &str| {
+            let picture = pictures.iter().find(|p| p.get_filename().ends_with(name));
+            picture.unwrap()
+        };
+        let nef = picture("DSC_0001.NEF");
+        let raf = picture("DSCF0001.RAF");
+        let orf = picture("P7080001.ORF");
+        assert_eq!("image/x-nikon-nef", nef.get_mime());
+        assert_eq!("image/x-fuji-raf", raf.get_mime());
+        assert_eq!("image/x-olympus-orf", orf.get_mime());
+
+        let metadata = library.get_metadata(nef).unwrap();
+        assert_eq!(Some("NIKON D750".to_string()), metadata.get_camera_model());
+        // The size of the sensor, not of the preview.
+        assert_eq!(Some(6016), metadata.get_width());
+        assert_eq!(Some(4016), metadata.get_height());
+        let metadata = library.get_metadata(orf).unwrap();
+        assert_eq!(Some("E-M1MarkII".to_string()), metadata.get_camera_model());
+        assert_eq!(Some(5184), metadata.get_width());
+        assert_eq!(Some(3888), metadata.get_height());
+        let metadata = library.get_metadata(raf).unwrap();
+        assert_eq!(Some(6240), metadata.get_width());
+        assert_eq!(Some(4160), metadata.get_height());
+        assert_eq!(Some("FUJIFILM".to_string()), metadata.get_camera_make());
+        assert_eq!(
+            "2024-05-06 07:08:09",
+            metadata.get_date_taken().unwrap().to_string()
+        );
+
+        // Thumbnails come from the 300x200 previews, even when they are too
+        // small for the profile.
+        let png = |size| ThumbnailProfile::new(size, ThumbnailFormat::Png, 100);
+        library
+            .set_thumbnail_profiles(&[png(128), png(512)])
+            .unwrap();
+        let report = library.generate_all_thumbnails().unwrap();
+        assert!(report.get_failed().is_empty());
+        for picture in library.list_all_pictures().unwrap() {
+            let small = image::image_dimensions(picture.thumbnail_for(128)).unwrap();
+            let large = image::image_dimensions(picture.thumbnail_for(512)).unwrap();
+            assert_eq!((128, 85), small);
+            assert_eq!((300, 200), large);
+        }
+    }
+
+    #[test]
+    fn picture_stacks() {
+        let dir = TempDir::new("lumenza").unwrap();
+
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().join("thumbnails/");
+        let database = dir.path().join("database.sqlite3");
+
+        let photos = dir.path().join("photos");
+        std::fs::create_dir(&photos).unwrap();
+        let copy = |from: &str, to: &str| std::fs::copy(from, photos.join(to)).unwrap();
+        copy("tests/fixtures/raw/DSC_0001.NEF", "DSC_0001.NEF");
+        copy("tests/images/trail.jpg", "DSC_0001.JPG");
+        copy("tests/images/lake.png", "lake.png");
+        copy("tests/images/lake.png", "lake (1).png");
+        copy("tests/fixtures/preview.jpg", "preview.jpg");
+
+        let mut library = Library::create(&config, &thumbnails, &database).unwrap();
+        library.process_folder(&photos).unwrap();
+        assert_eq!(5, library.list_all_pictures().unwrap().len());
+
+        // The RAW+JPEG pair and the edited copy were stacked.
+        let stacks = library.list_stacks().unwrap();
+        assert_eq!(2, stacks.len());
+        let pair = stacks
+            .iter()
+            .find(|s| s.get_kind() == StackKind::SameName)
+            .unwrap();
+        let pictures = library.list_stack_pictures(pair).unwrap();
+        assert_eq!(2, pictures.len());
+        assert!(pictures[0].get_filename().ends_with("DSC_0001.JPG"));
+        assert!(pictures[1].is_raw());
+        assert_eq!(pictures[0].get_id(), pair.get_top());
+
+        let mut edit = stacks
+            .iter()
+            .find(|s| s.get_kind() == StackKind::Edited)
+            .unwrap()
+            .clone();
+        let pictures = library.list_stack_pictures(&edit).unwrap();
+        assert!(pictures[0].get_filename().ends_with("lake (1).png"));
+        let (copy, lake) = (pictures[0].clone(), pictures[1].clone());
+
+        // Only the tops are listed.
+        let tops = library.list_stack_tops().unwrap();
+        assert_eq!(3, tops.len());
+        assert!(tops.iter().all(|p| !p.is_raw()));
+        assert_eq!(3, library.count(&Filter::StackTops).unwrap());
+        let stack = library.get_picture_stack(&pictures[1]).unwrap().unwrap();
+        assert_eq!(edit.get_id(), stack.get_id());
+
+        // Stacks left with a single picture are deleted.
+        library.remove_from_stack(&mut edit, &copy).unwrap();
+        assert_eq!(0, edit.get_picture_count());
+        assert_eq!(
+            LumenzaError::StackNotFound(),
+            library.get_stack(edit.get_id()).unwrap_err()
+        );
+        assert_eq!(
+            None,
+            library
+                .get_picture_stack(&lake)
+                .unwrap()
+                .map(|s| s.get_id())
+        );
+
+        // Scanning again doesn't stack them back.
+        library.process_folder(&photos).unwrap();
+        assert_eq!(1, library.list_stacks().unwrap().len());
+
+        let preview = tops
+            .iter()
+            .find(|p| p.get_filename().ends_with("preview.jpg"))
+            .unwrap();
+        let mut stack = library
+            .create_stack(&[lake.clone(), copy.clone(), preview.clone()], preview)
+            .unwrap();
+        assert_eq!(StackKind::Manual, stack.get_kind());
+        assert_eq!(3, stack.get_picture_count());
+        assert_eq!(preview.get_id(), stack.get_top());
+        assert_eq!(
+            LumenzaError::PictureAlreadyInStack(),
+            library
+                .create_stack(&[lake.clone(), tops[0].clone()], &lake)
+                .unwrap_err()
+        );
+        assert_eq!(
+            LumenzaError::InvalidStack(),
+            library
+                .create_stack(std::slice::from_ref(&lake), &lake)
+                .unwrap_err()
+        );
+        library.set_stack_top(&mut stack, &lake).unwrap();
+        assert_eq!(lake.get_id(), stack.get_top());
+        assert_eq!(
+            lake.get_id(),
+            library.list_stack_pictures(&stack).unwrap()[0].get_id()
+        );
+
+        // Removing the top picks the first remaining picture.
+        library.remove_from_stack(&mut stack, &lake).unwrap();
+        assert_eq!(2, stack.get_picture_count());
+        assert_eq!(copy.get_id().min(preview.get_id()), stack.get_top());
+
+        library.delete_stack(stack).unwrap();
+        assert_eq!(1, library.list_stacks().unwrap().len());
+        assert_eq!(4, library.list_stack_tops().unwrap().len());
+    }
+
+    #[test]
+    fn detect_file_types() {
+        let dir = TempDir::new("lumenza").unwrap();
+
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().join("thumbnails/");
+        let database = dir.path().join("database.sqlite3");
+
+        let photos = dir.path().join("photos");
+        std::fs::create_dir(&photos).unwrap();
+        std::fs::copy("tests/images/trail.jpg", photos.join("IMG_0002")).unwrap();
+        std::fs::copy("tests/images/lake.png", photos.join("lake.jpg")).unwrap();
+        std::fs::copy(
+            "tests/fixtures/raw/DSC_0001.NEF",
+            photos.join("DSC_0001.NEF"),
+        )
+        .unwrap();
+        std::fs::write(photos.join("notes.jpg"), "Not a picture").unwrap();
+        std::fs::write(photos.join("clip.mp4"), "Not a video either").unwrap();
+        // MP4 audio files, told apart by their brand or their extension.
+        let mp4 = |brand: &[u8]| [b"\0\0\0\x14ftyp", brand, b"\0\0\0\0isom"].concat();
+        std::fs::write(photos.join("song.m4a"), mp4(b"M4A ")).unwrap();
+        std::fs::write(photos.join("book.m4b"), mp4(b"M4B ")).unwrap();
+        std::fs::write(photos.join("voice.m4a"), mp4(b"isom")).unwrap();
+
+        let mut library = Library::create(&config, &thumbnails, &database).unwrap();
+        library.process_folder(&photos).unwrap();
+
+        let media = library.list_all_media().unwrap();
+        assert_eq!(3, media.len());
+        let mime = |name: &str| {
+            let picture = media
+                .iter()
+                .map(|media| media.get_entry())
+                .find(|p| p.get_filename().ends_with(name));
+            picture.unwrap().get_mime()
+        };
+        assert_eq!("image/jpeg", mime("IMG_0002"));
+        assert_eq!("image/png", mime("lake.jpg"));
+        assert_eq!("image/x-nikon-nef", mime("DSC_0001.NEF"));
+        assert_eq!(
+            1,
+            library
+                .count(&Filter::MimeType("image/png".to_string()))
+                .unwrap()
+        );
+
+        let report = library.generate_all_thumbnails().unwrap();
+        assert!(report.get_failed().is_empty());
+        assert_eq!(3, report.get_generated());
+    }
+
+    #[test]
+    fn live_photos() {
+        let dir = TempDir::new("lumenza").unwrap();
+
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().join("thumbnails/");
+        let database = dir.path().join("database.sqlite3");
+
+        // The video is synced before its still.
+        let photos = dir.path().join("photos");
+        std::fs::create_dir(&photos).unwrap();
+        let copy = |name: &str| {
+            let from = PathBuf::from("tests/fixtures/live").join(name);
+            std::fs::copy(from, photos.join(name)).unwrap();
+        };
+        copy("IMG_0001.MOV");
+        let mut library = Library::create(&config, &thumbnails, &database).unwrap();
+        library.process_folder(&photos).unwrap();
+        let video = library.list_all_videos().unwrap()[0].clone();
+        assert!(library.get_live_photo(&video).unwrap().is_none());
+
+        copy("IMG_0001.JPG");
+        copy("PXL_0001.MP.jpg");
+        library.process_folder(&photos).unwrap();
+        let pictures = library.list_all_pictures().unwrap();
+        let picture = |name: &str| {
+            let picture = pictures.iter().find(|p| p.get_filename().ends_with(name));
+            picture.unwrap()
+        };
+
+        // Apple Live Photos are paired through their content identifier.
+        let still = picture("IMG_0001.JPG");
+        match library.get_live_video(still).unwrap() {
+            Some(LiveVideo::Paired(paired)) => assert_eq!(video.get_id(), paired.get_id()),
+            other => panic!("Unexpected live video {:?}", other),
+        }
+        let paired = library.get_live_photo(&video).unwrap().unwrap();
+        assert_eq!(still.get_id(), paired.get_id());
+
+        // Android motion photos carry their video.
+        let motion = picture("PXL_0001.MP.jpg");
+        let (offset, length) = match library.get_live_video(motion).unwrap() {
+            Some(LiveVideo::Embedded { offset, length }) => (offset, length),
+            other => panic!("Unexpected live video {:?}", other),
+        };
+        let data = std::fs::read(motion.get_filename()).unwrap();
+        assert_eq!(data.len() as u64, offset + length);
+        assert_eq!(b"ftyp", &data[offset as usize + 4..offset as usize + 8]);
+
+        // Clients can show each Live Photo as a single item.
+        assert_eq!(2, library.count(&Filter::LivePhotos).unwrap());
+        assert_eq!(1, library.count(&Filter::LiveVideos).unwrap());
+        assert_eq!(2, library.count(&!Filter::LiveVideos).unwrap());
+        let trail = library
+            .add_picture(&PathBuf::from("tests/images/trail.jpg"))
+            .unwrap();
+        assert!(library.get_live_video(&trail).unwrap().is_none());
+    }
+
+    #[test]
+    fn watch_folders() {
+        let dir = TempDir::new("lumenza").unwrap();
+
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().join("thumbnails/");
+        let database = dir.path().join("database.sqlite3");
+
+        let photos = dir.path().join("photos");
+        std::fs::create_dir(&photos).unwrap();
+        std::fs::copy("tests/images/lake.png", photos.join("lake.png")).unwrap();
+        let mut library = Library::create(&config, &thumbnails, &database).unwrap();
+        library.process_folder(&photos).unwrap();
+        // A folder on a drive that was unplugged since.
+        let drive = dir.path().join("drive");
+        std::fs::create_dir(&drive).unwrap();
+        library.process_folder(&drive).unwrap();
+        std::fs::remove_dir(&drive).unwrap();
+
+        // Changes are made while the library watches its folders, each one
+        // once the previous one was seen.
+        let cancel = CancelToken::new();
+        let (seen, changes_seen) = std::sync::mpsc::channel();
+        let changes = {
+            let (photos, cancel) = (photos.clone(), cancel.clone());
+            std::thread::spawn(move || {
+                let seen_within = |seconds| {
+                    let timeout = std::time::Duration::from_secs(seconds);
+                    changes_seen.recv_timeout(timeout).is_ok()
+                };
+                // The watcher may not be up yet, so the first file is copied
+                // again until it is seen.
+                let mut seen = (0..5).any(|_| {
+                    std::fs::copy("tests/images/trail.jpg", photos.join("trail.jpg")).unwrap();
+                    seen_within(2)
+                });
+                let changes: [&dyn Fn(); 3] = [
+                    &|| {
+                        std::fs::rename(photos.join("lake.png"), photos.join("renamed.png"))
+                            .unwrap()
+                    },
+                    &|| {
+                        std::fs::copy("tests/fixtures/preview.jpg", photos.join("trail.jpg"))
+                            .unwrap();
+                    },
+                    &|| std::fs::remove_file(photos.join("renamed.png")).unwrap(),
+                ];
+                for change in changes {
+                    if !seen {
+                        break;
+                    }
+                    change();
+                    seen = seen_within(10);
+                }
+                cancel.cancel();
+            })
+        };
+        let mut events = Vec::new();
+        let on_event = |event: WatchEvent| {
+            let _ = seen.send(());
+            events.push(event);
+        };
+        library.watch(on_event, &cancel).unwrap();
+        changes.join().unwrap();
+
+        assert_eq!(4, events.len(), "{:?}", events);
+        let trail = match &events[0] {
+            WatchEvent::Added(picture) => picture.clone(),
+            other => panic!("Unexpected event {:?}", other),
+        };
+        assert_eq!(photos.join("trail.jpg"), trail.get_filename());
+        match &events[1] {
+            WatchEvent::Moved { from, picture } => {
+                assert_eq!(&photos.join("lake.png"), from);
+                assert_eq!(photos.join("renamed.png"), picture.get_filename());
+            }
+            other => panic!("Unexpected event {:?}", other),
+        }
+        match &events[2] {
+            WatchEvent::Modified(picture) => {
+                assert_eq!(trail.get_id(), picture.get_id());
+                assert_ne!(trail.get_hash(), picture.get_hash());
+                let metadata = library.get_metadata(picture).unwrap();
+                assert_eq!(Some("Canon".to_string()), metadata.get_camera_make());
+            }
+            other => panic!("Unexpected event {:?}", other),
+        }
+        match &events[3] {
+            WatchEvent::Removed(picture) => assert!(picture.is_missing()),
+            other => panic!("Unexpected event {:?}", other),
+        }
+        assert_eq!(1, library.list_all_pictures().unwrap().len());
+        assert_eq!(1, library.list_missing().unwrap().len());
+    }
+
+    #[test]
+    fn incremental_rescan() {
+        let dir = TempDir::new("lumenza").unwrap();
+
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().join("thumbnails/");
+        let database = dir.path().join("database.sqlite3");
+
+        let photos = dir.path().join("photos");
+        std::fs::create_dir(&photos).unwrap();
+        std::fs::copy("tests/images/lake.png", photos.join("lake.png")).unwrap();
+        std::fs::copy("tests/images/trail.jpg", photos.join("trail.jpg")).unwrap();
+        let mut library = Library::create(&config, &thumbnails, &database).unwrap();
+        library.process_folder(&photos).unwrap();
+        let find = |library: &Library, name: &str| {
+            let pictures = library.list_all_pictures().unwrap();
+            let picture = pictures.iter().find(|p| p.get_filename().ends_with(name));
+            picture.unwrap().clone()
+        };
+        let lake = find(&library, "lake.png");
+        let trail = find(&library, "trail.jpg");
+
+        // Files with the same size and modification time are not read again,
+        // even if their content changed behind Lumenza's back.
+        let modified = std::fs::metadata(photos.join("lake.png"))
+            .unwrap()
+            .modified()
+            .unwrap();
+        let mut data = std::fs::read(photos.join("lake.png")).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 0xff;
+        std::fs::write(photos.join("lake.png"), &data).unwrap();
+        let file = std::fs::File::options()
+            .write(true)
+            .open(photos.join("lake.png"))
+            .unwrap();
+        file.set_modified(modified).unwrap();
+
+        // Modified files get their hash and metadata refreshed.
+        std::fs::copy("tests/fixtures/preview.jpg", photos.join("trail.jpg")).unwrap();
+        library.process_folder(&photos).unwrap();
+
+        assert_eq!(2, library.list_all_pictures().unwrap().len());
+        assert_eq!(lake.get_hash(), find(&library, "lake.png").get_hash());
+        let updated = find(&library, "trail.jpg");
+        assert_eq!(trail.get_id(), updated.get_id());
+        assert_ne!(trail.get_hash(), updated.get_hash());
+        let metadata = library.get_metadata(&updated).unwrap();
+        assert_eq!(Some("Canon".to_string()), metadata.get_camera_make());
+        assert_eq!(Some(600), metadata.get_width());
+    }
+
+    #[test]
+    fn scan_report() {
+        let dir = TempDir::new("lumenza").unwrap();
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().join("thumbnails/");
+        let database = dir.path().join("database.sqlite3");
+
+        let pictures = dir.path().join("pictures");
+        std::fs::create_dir(&pictures).unwrap();
+        std::fs::copy("tests/images/trail.jpg", pictures.join("trail.jpg")).unwrap();
+        std::fs::copy("tests/images/lake.png", pictures.join("lake.png")).unwrap();
+        std::fs::write(pictures.join("notes.txt"), "Not a picture").unwrap();
+        let mut library = Library::create(&config, &thumbnails, &database).unwrap();
+
+        let report = library.process_folder(&pictures).unwrap();
+        assert_eq!(2, report.get_added().len());
+        assert!(report.get_updated().is_empty());
+        assert!(report.get_skipped().is_empty());
+        assert!(report.get_unreadable().is_empty());
+        assert_eq!(&[pictures.join("notes.txt")], report.get_unsupported());
+
+        // Unchanged files are skipped, files that are back are updated.
+        std::fs::rename(pictures.join("lake.png"), dir.path().join("lake.png")).unwrap();
+        library.verify().unwrap();
+        std::fs::rename(dir.path().join("lake.png"), pictures.join("lake.png")).unwrap();
+        let report = library.process_folder(&pictures).unwrap();
+        assert!(report.get_added().is_empty());
+        assert_eq!(1, report.get_updated().len());
+        assert!(report.get_updated()[0].get_filename().ends_with("lake.png"));
+        assert_eq!(&[pictures.join("trail.jpg")], report.get_skipped());
+    }
+
+    #[test]
+    fn library_events() {
+        let dir = TempDir::new("lumenza").unwrap();
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().join("thumbnails/");
+        let database = dir.path().join("database.sqlite3");
+
+        let pictures = dir.path().join("pictures");
+        std::fs::create_dir(&pictures).unwrap();
+        std::fs::copy("tests/images/trail.jpg", pictures.join("trail.jpg")).unwrap();
+        let mut library = Library::create(&config, &thumbnails, &database).unwrap();
+        let events = library.subscribe();
+        let dropped = library.subscribe();
+        drop(dropped);
+
+        library.process_folder(&pictures).unwrap();
+        let picture = match events.try_recv().unwrap() {
+            LibraryEvent::PictureAdded(picture) => picture,
+            other => panic!("Unexpected event {:?}", other),
+        };
+        assert!(picture.get_filename().ends_with("trail.jpg"));
+
+        library.generate_all_thumbnails().unwrap();
+        match events.try_recv().unwrap() {
+            LibraryEvent::ThumbnailReady(ready) => {
+                assert_eq!(picture.get_id(), ready.get_id());
+                assert!(ready.get_thumbnail().is_file());
+            }
+            other => panic!("Unexpected event {:?}", other),
+        }
+
+        library.set_rating(&picture, 4).unwrap();
+        assert!(matches!(
+            events.try_recv().unwrap(),
+            LibraryEvent::MetadataChanged(_)
+        ));
+        let mut album = library.create_album("Hikes").unwrap();
+        library.add_to_album(&mut album, &picture).unwrap();
+        for _ in 0..2 {
+            match events.try_recv().unwrap() {
+                LibraryEvent::AlbumChanged(id) => assert_eq!(album.get_id(), id),
+                other => panic!("Unexpected event {:?}", other),
+            }
+        }
+
+        // Tags and stacks.
+        let tag = library.create_tag("Hikes").unwrap();
+        assert!(matches!(
+            events.try_recv().unwrap(),
+            LibraryEvent::TagChanged(id) if id == tag.get_id()
+        ));
+        library.tag_picture(&picture, &tag).unwrap();
+        library.untag_picture(&picture, &tag).unwrap();
+        library.tag_picture(&picture, &tag).unwrap();
+        library.delete_tag(tag.clone()).unwrap();
+        let changed: Vec<LibraryEvent> = events.try_iter().collect();
+        assert_eq!(5, changed.len(), "{:?}", changed);
+        for (index, event) in changed.into_iter().enumerate() {
+            match event {
+                LibraryEvent::TagChanged(id) if index == 3 => assert_eq!(tag.get_id(), id),
+                LibraryEvent::MetadataChanged(tagged) if index != 3 => {
+                    assert_eq!(picture.get_id(), tagged.get_id())
+                }
+                other => panic!("Unexpected event {:?}", other),
+            }
+        }
+        std::fs::copy("tests/images/lake.png", pictures.join("lake.png")).unwrap();
+        let lake = library.add_picture(&pictures.join("lake.png")).unwrap();
+        assert!(matches!(
+            events.try_recv().unwrap(),
+            LibraryEvent::PictureAdded(_)
+        ));
+        let mut stack = library
+            .create_stack(&[picture.clone(), lake.clone()], &picture)
+            .unwrap();
+        library.set_stack_top(&mut stack, &lake).unwrap();
+        library.delete_stack(stack.clone()).unwrap();
+        for _ in 0..3 {
+            match events.try_recv().unwrap() {
+                LibraryEvent::StackChanged(id) => assert_eq!(stack.get_id(), id),
+                other => panic!("Unexpected event {:?}", other),
+            }
+        }
+
+        // Receivers can live on another thread.
+        let listener = std::thread::spawn(move || (events.recv().unwrap(), events));
+        std::fs::rename(pictures.join("trail.jpg"), pictures.join("path.jpg")).unwrap();
+        library.process_folder(&pictures).unwrap();
+        let (event, events) = listener.join().unwrap();
+        let moved = match event {
+            LibraryEvent::PictureMoved {
+                from,
+                picture: moved,
+            } => {
+                assert_eq!(pictures.join("trail.jpg"), from);
+                assert_eq!(pictures.join("path.jpg"), moved.get_filename());
+                moved
+            }
+            other => panic!("Unexpected event {:?}", other),
+        };
+
+        // Albums left with gaps by deleted pictures are renumbered, and
+        // stacks left with a single picture are deleted.
+        let stack = library.create_stack(&[moved, lake.clone()], &lake).unwrap();
+        assert!(matches!(
+            events.try_recv().unwrap(),
+            LibraryEvent::StackChanged(_)
+        ));
+        library.remove_folder(&pictures, false).unwrap();
+        let changed: Vec<LibraryEvent> = events.try_iter().collect();
+        assert_eq!(4, changed.len(), "{:?}", changed);
+        assert!(matches!(changed[2], LibraryEvent::AlbumChanged(id) if id == album.get_id()));
+        assert!(matches!(changed[3], LibraryEvent::StackChanged(id) if id == stack.get_id()));
+    }
+
+    #[test]
+    fn remove_folder() {
+        let dir = TempDir::new("lumenza").unwrap();
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().join("thumbnails/");
+        let database = dir.path().join("database.sqlite3");
+
+        let phone = dir.path().join("phone");
+        let laptop = dir.path().join("laptop");
+        std::fs::create_dir(&phone).unwrap();
+        std::fs::create_dir(&laptop).unwrap();
+        std::fs::copy("tests/images/trail.jpg", phone.join("trail.jpg")).unwrap();
+        std::fs::copy("tests/images/lake.png", laptop.join("lake.png")).unwrap();
+        let mut library = Library::create(&config, &thumbnails, &database).unwrap();
+        library.process_folder(&phone).unwrap();
+        library.process_folder(&laptop).unwrap();
+        library.generate_all_thumbnails().unwrap();
+
+        let trail = library
+            .query(&Query::new().filter(Filter::folder(&phone)))
+            .unwrap()[0]
+            .clone();
+        let tag = library.create_tag("Places/Mountains").unwrap();
+        library.tag_picture(&trail, &tag).unwrap();
+        let mut album = library.create_album("Hikes").unwrap();
+        library.add_to_album(&mut album, &trail).unwrap();
+
+        // Kept pictures are found again when the folder is added back.
+        let report = library.remove_folder(&phone, true).unwrap();
+        assert!(report.get_deleted().is_empty());
+        assert_eq!(trail.get_id(), report.get_detached()[0].get_id());
+        assert_eq!(vec![laptop.clone()], library.list_all_folders());
+        assert_eq!(1, library.list_all_pictures().unwrap().len());
+        assert_eq!(1, library.list_stack_tops().unwrap().len());
+        assert_eq!(1, library.query(&Query::new()).unwrap().len());
+        assert_eq!(1, library.count(&!Filter::Missing).unwrap());
+        assert_eq!(0, library.count(&Filter::Missing).unwrap());
+        // Verifying and purging the library leaves them alone, even though
+        // their files are still there.
+        let report = library.verify().unwrap();
+        assert!(report.get_reattached().is_empty());
+        assert!(report.get_missing().is_empty());
+        assert_eq!(0, library.purge_missing().unwrap());
+        assert_eq!(1, library.list_all_pictures().unwrap().len());
+        assert!(library.get_picture(trail.get_id()).unwrap().is_missing());
+        let report = library.process_folder(&phone).unwrap();
+        assert_eq!(trail.get_id(), report.get_updated()[0].get_id());
+        assert_eq!(1, library.list_tagged_pictures(&tag, false).unwrap().len());
+
+        // Otherwise they are gone, along with their thumbnails.
+        let thumbnail = library.get_picture(trail.get_id()).unwrap().get_thumbnail();
+        assert!(thumbnail.is_file());
+        let report = library.remove_folder(&phone, false).unwrap();
+        assert_eq!(trail.get_id(), report.get_deleted()[0].get_id());
+        assert!(report.get_removed_thumbnails() > 0);
+        assert!(!thumbnail.exists());
+        assert!(phone.join("trail.jpg").is_file());
+        assert_eq!(
+            LumenzaError::PictureNotFound(),
+            library.get_picture(trail.get_id()).unwrap_err()
+        );
+        assert_eq!(
+            0,
+            library
+                .get_album(album.get_id())
+                .unwrap()
+                .get_picture_count()
+        );
+        assert!(library
+            .list_tagged_pictures(&tag, false)
+            .unwrap()
+            .is_empty());
+
+        assert_eq!(
+            LumenzaError::FolderNotFound(),
+            library.remove_folder(&phone, false).unwrap_err()
+        );
+        let library = Library::open(&config).unwrap();
+        assert_eq!(vec![laptop], library.list_all_folders());
+    }
+
+    #[test]
+    fn shared_thumbnail_folder() {
+        let dir = TempDir::new("lumenza").unwrap();
+
+        // Thumbnails right next to the config and the database.
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().to_path_buf();
+        let database = dir.path().join("database.sqlite3");
+
+        let mut library = Library::create(&config, &thumbnails, &database).unwrap();
+        library
+            .process_folder(&PathBuf::from("tests/images/"))
+            .unwrap();
+        let foreign = [
+            dir.path().join("notes.txt"),
+            dir.path().join("ab").join("holiday.jpg"),
+            dir.path().join("00").join("not-a-hash-256.jpg"),
+        ];
+        for file in &foreign {
+            std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+            std::fs::write(file, "Not a thumbnail").unwrap();
+        }
+        let orphan = dir
+            .path()
+            .join("00")
+            .join(format!("{}-256.jpg", "0".repeat(64)));
+        std::fs::write(&orphan, "Old thumbnail").unwrap();
+
+        let report = library.generate_all_thumbnails().unwrap();
+        assert_eq!(2, report.get_generated());
+        assert!(!orphan.exists());
+        for file in foreign.iter().chain([&config, &database]) {
+            assert!(file.is_file(), "{} was deleted", file.display());
+        }
+        for picture in library.list_all_pictures().unwrap() {
+            assert!(picture.get_thumbnail().is_file());
+        }
+        library.purge_missing().unwrap();
+        Library::open(&config).unwrap();
+    }
+
+    #[test]
+    fn thumbnail_folder_with_newline() {
+        let dir = TempDir::new("lumenza").unwrap();
+
+        let config = dir.path().join("default.conf");
+        let thumbnails = dir.path().join("thumbnails\nof pictures:256/");
+        let database = dir.path().join("database.sqlite3");
+
+        let mut library = Library::create(&config, &thumbnails, &database).unwrap();
+        library
+            .process_folder(&PathBuf::from("tests/images/"))
+            .unwrap();
+        library.generate_all_thumbnails().unwrap();
+        for picture in library.list_all_pictures().unwrap() {
+            let thumbnail = picture.thumbnail_for(256);
+            assert!(thumbnail.starts_with(&thumbnails));
+            assert!(thumbnail.is_file());
+        }
     }
 }